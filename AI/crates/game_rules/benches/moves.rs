@@ -0,0 +1,72 @@
+//! Move generation and application on positions from the middle and end of games, the two
+//! things search spends most of its time on. Cloning is timed on its own too, since every
+//! move applied starts with a copy of the state.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use game_rules::spice::*;
+use mcts::GameState;
+use rand::{rngs::StdRng, seq::IteratorRandom, SeedableRng};
+
+/// 20 unfinished positions `move_count` random moves into a game, the same every run.
+fn positions(move_count: u16) -> Vec<SpiceState> {
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut states = Vec::new();
+
+    while states.len() < 20 {
+        let mut state = SpiceState::initial_state();
+        for _ in 0..move_count {
+            match state.available_moves().choose(&mut rng) {
+                Some(move_) => state = state.apply_move(&move_),
+                None => break,
+            }
+        }
+
+        if state.available_moves().next().is_some() {
+            states.push(state);
+        }
+    }
+
+    states
+}
+
+fn moves(c: &mut Criterion) {
+    for move_count in [40, 200] {
+        let states = positions(move_count);
+        let moves: Vec<Vec<_>> = states
+            .iter()
+            .map(|s| s.available_moves().collect())
+            .collect();
+
+        c.bench_function(&format!("available_moves after {move_count}"), |b| {
+            b.iter(|| {
+                states
+                    .iter()
+                    .map(|s| s.available_moves().count())
+                    .sum::<usize>()
+            })
+        });
+
+        c.bench_function(&format!("clone after {move_count}"), |b| {
+            b.iter(|| {
+                for (state, moves) in states.iter().zip(&moves) {
+                    for _ in moves {
+                        black_box(state.clone());
+                    }
+                }
+            })
+        });
+
+        c.bench_function(&format!("apply_move after {move_count}"), |b| {
+            b.iter(|| {
+                for (state, moves) in states.iter().zip(&moves) {
+                    for move_ in moves {
+                        black_box(state.apply_move(move_));
+                    }
+                }
+            })
+        });
+    }
+}
+
+criterion_group!(benches, moves);
+criterion_main!(benches);
@@ -0,0 +1,41 @@
+//! Feeds arbitrary moves, legal or not, from either player through the checked move API,
+//! starting from the standard position. Whatever comes in, nothing should panic, only legal
+//! moves should be let through, and the position should stay valid.
+//!
+//! Run with `cargo +nightly fuzz run checked_moves` from `crates/game_rules`.
+
+#![no_main]
+
+use game_rules::spice::{virt_d3, Direction, SpiceMove, SpicePlayer, SpiceState};
+use libfuzzer_sys::fuzz_target;
+use mcts::GameState;
+
+fuzz_target!(|data: &[u8]| {
+    let mut state = SpiceState::initial_state();
+
+    // five bytes to a move: the player, the source's coordinates and the direction. The
+    // coordinates are kept near the grid so that most moves aren't just out of bounds
+    for bytes in data.chunks_exact(5) {
+        let player = if bytes[0] % 2 == 0 {
+            SpicePlayer::Blue
+        } else {
+            SpicePlayer::Red
+        };
+        let [i, j, k] = [bytes[1], bytes[2], bytes[3]].map(|b| (b as i8) % 8);
+        let move_ = SpiceMove::new(
+            virt_d3(i, j, k),
+            Direction::ALL[bytes[4] as usize % Direction::ALL.len()],
+        );
+
+        let legal = player == state.next_to_play() && state.available_moves().any(|m| m == move_);
+
+        match state.try_apply_move(player, &move_) {
+            Ok(next) => {
+                assert!(legal, "{move_} shouldn't be allowed in {state}");
+                assert_eq!(next.validate(), vec![], "{move_} broke {state}");
+                state = next;
+            }
+            Err(_) => assert!(!legal, "{move_} should be allowed in {state}"),
+        }
+    }
+});
@@ -0,0 +1,280 @@
+//! Post-game analysis: replays a finished game, searches every position in it, and judges
+//! each move by how much worse it was than the best move the search could find.
+
+use mcts::{solve, GameState, SearchParameters, Searcher};
+use serde::{Deserialize, Serialize};
+
+use super::{moves::*, players::*, record::*, SpiceState};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnalysisParameters {
+    /// Budget for each position. If [SearchParameters::solver_node_budget] is set, positions
+    /// that can be solved are scored exactly instead of searched.
+    pub search: SearchParameters,
+    pub thresholds: ClassificationThresholds,
+}
+
+/// The smallest value drop (on the -1 to 1 scale of [GameState::terminal_value]) that
+/// counts as each kind of bad move.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct ClassificationThresholds {
+    pub inaccuracy: f32,
+    pub mistake: f32,
+    pub blunder: f32,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum MoveClassification {
+    /// The move the analysis would have played.
+    Best,
+    Good,
+    Inaccuracy,
+    Mistake,
+    Blunder,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct MoveAnalysis {
+    pub move_count: u16,
+    pub player: SpicePlayer,
+    pub played_move: SpiceMove,
+    /// Value of the position after `played_move`, for `player`.
+    pub played_value: f32,
+    pub best_move: SpiceMove,
+    /// Value of the position after `best_move`, for `player`.
+    pub best_value: f32,
+    /// How much `player` lost by not playing `best_move`. Never negative.
+    pub value_drop: f32,
+    pub classification: MoveClassification,
+    /// Blue's chances after `played_move`, between 0 and 1, for plotting over the game.
+    pub blue_win_probability: f32,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct GameAnalysis {
+    pub moves: Vec<MoveAnalysis>,
+    /// The final position's result for Blue, or [None] if the game was unfinished.
+    pub result: Option<f32>,
+}
+
+impl Default for ClassificationThresholds {
+    fn default() -> Self {
+        Self {
+            inaccuracy: 0.1,
+            mistake: 0.25,
+            blunder: 0.5,
+        }
+    }
+}
+
+impl ClassificationThresholds {
+    pub fn classify(&self, value_drop: f32, played_best: bool) -> MoveClassification {
+        if played_best {
+            MoveClassification::Best
+        } else if value_drop >= self.blunder {
+            MoveClassification::Blunder
+        } else if value_drop >= self.mistake {
+            MoveClassification::Mistake
+        } else if value_drop >= self.inaccuracy {
+            MoveClassification::Inaccuracy
+        } else {
+            MoveClassification::Good
+        }
+    }
+}
+
+/// Replays `moves` from [SpiceState::initial_state] and analyzes each one.
+pub fn analyze_game(
+    moves: &[SpiceMove],
+    parameters: &AnalysisParameters,
+) -> Result<GameAnalysis, ReplayError> {
+    analyze_game_from(SpiceState::initial_state(), moves, parameters)
+}
+
+fn analyze_game_from(
+    start: SpiceState,
+    moves: &[SpiceMove],
+    parameters: &AnalysisParameters,
+) -> Result<GameAnalysis, ReplayError> {
+    // replay everything up front, so that bad input fails before any searching happens
+    let states = replay_moves(start, moves)?;
+
+    // each position's value, for the player to move, along with the best move from it
+    let evaluations: Vec<(f32, Option<SpiceMove>)> = states
+        .iter()
+        .map(|state| evaluate(state, &parameters.search))
+        .collect();
+
+    let analyses = moves
+        .iter()
+        .enumerate()
+        .map(|(ply, played_move)| {
+            let state = &states[ply];
+            let (best_value, best_move) = evaluations[ply].clone();
+            let best_move = best_move.expect("non-terminal positions should have a best move");
+
+            // players alternate, so the next position's value is from the opponent's side
+            let played_value = -evaluations[ply + 1].0;
+            let value_drop = (best_value - played_value).max(0.);
+            let played_best = *played_move == best_move;
+
+            let blue_value = match state.player {
+                SpicePlayer::Blue => played_value,
+                SpicePlayer::Red => -played_value,
+            };
+
+            MoveAnalysis {
+                move_count: state.move_count,
+                player: state.player,
+                played_move: played_move.clone(),
+                played_value,
+                best_move,
+                best_value,
+                value_drop,
+                classification: parameters.thresholds.classify(value_drop, played_best),
+                blue_win_probability: (blue_value + 1.) / 2.,
+            }
+        })
+        .collect();
+
+    let last = states.last().unwrap();
+    Ok(GameAnalysis {
+        moves: analyses,
+        result: last.terminal_value(SpicePlayer::Blue),
+    })
+}
+
+fn evaluate(state: &SpiceState, parameters: &SearchParameters) -> (f32, Option<SpiceMove>) {
+    if let Some(value) = state.terminal_value(state.player) {
+        return (value, None);
+    }
+
+    if let Some(solution) = parameters.solver_node_budget.and_then(|b| solve(state, b)) {
+        return (solution.value, solution.best_move);
+    }
+
+    let mut searcher = Searcher::new(SearchParameters {
+        solver_node_budget: None,
+        ..parameters.clone()
+    });
+    searcher.search(state.clone());
+
+    let stats = searcher
+        .root_statistics()
+        .expect("searching without the solver should always build a tree");
+    let best = stats
+        .children
+        .iter()
+        .max_by_key(|c| c.visits)
+        .expect("non-terminal positions should have moves");
+
+    // children are scored for the player who moves into them, which is the player to move
+    (best.score / best.visits as f32, Some(best.move_.clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::*;
+
+    use super::*;
+    use crate::spice::{coord::*, direction::*, fixtures::*, grid::*};
+
+    #[fixture]
+    fn parameters() -> AnalysisParameters {
+        AnalysisParameters {
+            search: SearchParameters {
+                exploration_factor: std::f32::consts::FRAC_1_SQRT_2,
+                search_iterations: 4,
+                solver_node_budget: Some(1_000),
+                rollout_cutoff: None,
+            },
+            thresholds: Default::default(),
+        }
+    }
+
+    #[rstest]
+    #[case(0., true, MoveClassification::Best)]
+    #[case(1., true, MoveClassification::Best)]
+    #[case(0.05, false, MoveClassification::Good)]
+    #[case(0.1, false, MoveClassification::Inaccuracy)]
+    #[case(0.3, false, MoveClassification::Mistake)]
+    #[case(2., false, MoveClassification::Blunder)]
+    fn classification_follows_thresholds(
+        #[case] value_drop: f32,
+        #[case] played_best: bool,
+        #[case] expected: MoveClassification,
+    ) {
+        let thresholds = ClassificationThresholds::default();
+        assert_eq!(thresholds.classify(value_drop, played_best), expected);
+    }
+
+    #[rstest]
+    fn analysis_covers_every_move(parameters: AnalysisParameters) {
+        let moves = first_moves(&SpiceState::initial_state(), 2);
+        let analysis = analyze_game(&moves, &parameters).unwrap();
+
+        assert_eq!(analysis.moves.len(), 2);
+        assert_eq!(analysis.result, None);
+        for (ply, m) in analysis.moves.iter().enumerate() {
+            assert_eq!(m.move_count as usize, ply);
+            assert_eq!(m.played_move, moves[ply]);
+            assert!(m.value_drop >= 0.);
+            assert!((0. ..=1.).contains(&m.blue_win_probability));
+        }
+    }
+
+    #[rstest]
+    fn solved_games_are_scored_exactly(parameters: AnalysisParameters, small_state: SpiceState) {
+        let moves = first_moves(&small_state, usize::MAX);
+        let analysis = analyze_game_from(small_state, &moves, &parameters).unwrap();
+
+        let result = analysis.result.expect("the game should have finished");
+        let last = analysis.moves.last().unwrap();
+        assert_eq!(last.blue_win_probability, (result + 1.) / 2.);
+    }
+
+    #[rstest]
+    #[case(Direction::SouthEast, 0., MoveClassification::Best)]
+    #[case(Direction::UpNorth, -1., MoveClassification::Blunder)]
+    fn searched_moves_are_classified_by_their_result(
+        mut parameters: AnalysisParameters,
+        #[case] direction: Direction,
+        #[case] played_value: f32,
+        #[case] expected: MoveClassification,
+        blockable_center_state: SpiceState,
+    ) {
+        parameters.search.solver_node_budget = None;
+        parameters.search.search_iterations = 50;
+
+        let move_ = SpiceMove::new(virt_d3(1, -2, 0), direction);
+        let analysis = analyze_game_from(blockable_center_state, &[move_], &parameters).unwrap();
+
+        let analysis = &analysis.moves[0];
+        assert_eq!(analysis.best_value, 0.);
+        assert_eq!(analysis.played_value, played_value);
+        assert_eq!(analysis.classification, expected);
+    }
+
+    #[rstest]
+    fn illegal_moves_are_rejected(parameters: AnalysisParameters) {
+        let mut moves = first_moves(&SpiceState::initial_state(), 1);
+        moves.push(moves[0].clone());
+
+        assert!(matches!(
+            analyze_game(&moves, &parameters),
+            Err(ReplayError::IllegalMove { ply: 1, .. })
+        ));
+    }
+
+    #[rstest]
+    fn moves_after_the_end_are_rejected(parameters: AnalysisParameters, small_state: SpiceState) {
+        let mut moves = first_moves(&small_state, usize::MAX);
+        moves.push(moves[0].clone());
+        let ply = moves.len() - 1;
+
+        assert_eq!(
+            analyze_game_from(small_state, &moves, &parameters),
+            Err(ReplayError::GameAlreadyOver { ply })
+        );
+    }
+}
@@ -0,0 +1,164 @@
+//! Board layouts, so that different Spice arenas can be designed as data instead of code.
+//! The same JSON can be read by the frontend to generate the board it displays.
+
+use std::{fs, io, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use super::{coord::*, grid::*, players::*};
+
+/// Radius of the largest board a [SpiceConfig] can describe. Bigger boards would overflow
+/// coordinate math.
+pub const MAX_RADIUS: f32 = 32.;
+
+pub const DEFAULT_MAX_MOVES: u16 = 400;
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct SpiceConfig {
+    /// Every space with a virtual length less than this is part of the board.
+    pub radius: f32,
+    pub blue_endpoints: Vec<VirtD3>,
+    pub red_endpoints: Vec<VirtD3>,
+    /// Spaces that are blocked before the game starts.
+    #[serde(default)]
+    pub blocked: Vec<VirtD3>,
+    /// The game is a draw once this many moves have been played.
+    pub max_moves: u16,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ConfigError {
+    /// The radius isn't between 1 and [MAX_RADIUS].
+    InvalidRadius(f32),
+    /// A starting endpoint or blocked space is outside the board.
+    OutOfBounds(VirtD3),
+    /// More than one starting endpoint or blocked space is on the same space.
+    Overlapping(VirtD3),
+    /// Something was placed on the center, which has to start empty.
+    CenterOccupied,
+    /// A player has no starting endpoints, so could never move.
+    NoEndpoints(SpicePlayer),
+}
+
+impl Default for SpiceConfig {
+    fn default() -> Self {
+        Self {
+            radius: GRID_CONSTANT_F,
+            blue_endpoints: vec![virt_d3(3, 3, 3)],
+            red_endpoints: vec![virt_d3(-3, -3, -3)],
+            blocked: Vec::new(),
+            max_moves: DEFAULT_MAX_MOVES,
+        }
+    }
+}
+
+impl SpiceConfig {
+    pub fn endpoints(&self, player: SpicePlayer) -> &[VirtD3] {
+        match player {
+            SpicePlayer::Blue => &self.blue_endpoints,
+            SpicePlayer::Red => &self.red_endpoints,
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(serde_json::from_slice(&fs::read(path)?)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, serde_json::to_vec_pretty(self)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mcts::GameState;
+    use rstest::*;
+
+    use super::*;
+    use crate::spice::SpiceState;
+
+    fn with_blocked(blocked: Vec<VirtD3>) -> SpiceConfig {
+        SpiceConfig {
+            blocked,
+            ..Default::default()
+        }
+    }
+
+    #[rstest]
+    fn default_config_is_the_initial_state() {
+        assert_eq!(
+            SpiceState::from_config(&SpiceConfig::default()),
+            Ok(SpiceState::initial_state())
+        );
+    }
+
+    #[rstest]
+    #[case(1.5, 19)]
+    #[case(2., 27)]
+    #[case(GRID_CONSTANT_F, Grid::default().enumerate_vc().count())]
+    fn radius_sets_board_size(#[case] radius: f32, #[case] spaces: usize) {
+        let config = SpiceConfig {
+            radius,
+            blue_endpoints: vec![virt_d3(1, 0, 0)],
+            red_endpoints: vec![virt_d3(-1, 0, 0)],
+            ..Default::default()
+        };
+
+        let state = SpiceState::from_config(&config).unwrap();
+        assert_eq!(state.grid.enumerate_vc().count(), spaces);
+    }
+
+    #[rstest]
+    #[case(with_blocked(vec![virt_d3(1, 2, 3), virt_d3(-2, 0, 1)]), Ok(()))]
+    #[case(SpiceConfig { radius: 0.5, ..Default::default() }, Err(ConfigError::InvalidRadius(0.5)))]
+    #[case(SpiceConfig { radius: f32::NAN, ..Default::default() }, Err(ConfigError::InvalidRadius(f32::NAN)))]
+    #[case(with_blocked(vec![virt_d3(5, 5, 5)]), Err(ConfigError::OutOfBounds(virt_d3(5, 5, 5))))]
+    #[case(with_blocked(vec![virt_d3(3, 3, 3)]), Err(ConfigError::Overlapping(virt_d3(3, 3, 3))))]
+    #[case(with_blocked(vec![virt_d3(0, 0, 0)]), Err(ConfigError::CenterOccupied))]
+    #[case(SpiceConfig { red_endpoints: vec![], ..Default::default() }, Err(ConfigError::NoEndpoints(SpicePlayer::Red)))]
+    fn configs_are_validated(
+        #[case] config: SpiceConfig,
+        #[case] expected: Result<(), ConfigError>,
+    ) {
+        let result = SpiceState::from_config(&config).map(|_| ());
+
+        // NaN never equals itself, so compare the debug output instead
+        assert_eq!(format!("{result:?}"), format!("{expected:?}"));
+    }
+
+    #[rstest]
+    fn blocked_spaces_are_placed() {
+        let state = SpiceState::from_config(&with_blocked(vec![virt_d3(1, 2, 3)])).unwrap();
+
+        assert_eq!(
+            state.grid.get_vc(virt_d3(1, 2, 3)),
+            Some(&GridSpace::Blocked)
+        );
+    }
+
+    #[rstest]
+    fn max_moves_ends_the_game() {
+        let config = SpiceConfig {
+            max_moves: 1,
+            ..Default::default()
+        };
+
+        let state = SpiceState::from_config(&config).unwrap();
+        let move_ = state.available_moves().next().unwrap();
+
+        assert_eq!(
+            state.apply_move(&move_).terminal_value(SpicePlayer::Blue),
+            Some(0.)
+        );
+    }
+
+    #[rstest]
+    fn json_round_trip() {
+        let config = with_blocked(vec![virt_d3(1, 2, 3)]);
+
+        let json = serde_json::to_string(&config).unwrap();
+        let loaded: SpiceConfig = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(loaded, config);
+    }
+}
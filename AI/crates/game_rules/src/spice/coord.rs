@@ -3,13 +3,15 @@ use std::{
     ops::*,
 };
 
+use serde::{Deserialize, Serialize};
+
 pub trait Coord: Sized + Copy {
     fn length_squared(self) -> f32;
     fn length(self) -> f32;
 }
 
 /// A point in Z3. May be convertable into a [VirtD3].
-#[derive(PartialEq, Eq, Clone, Copy)]
+#[derive(PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
 pub struct Real {
     pub x: i16,
     pub y: i16,
@@ -18,7 +20,7 @@ pub struct Real {
 
 /// A virtual point with integral components which corresponds to a point in the D3 (aka
 /// FCC, A3) lattice. The lattice point can be retrieved via conversion to a [Real].
-#[derive(PartialEq, Eq, Clone, Copy)]
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
 pub struct VirtD3 {
     pub i: i8,
     pub j: i8,
@@ -277,6 +279,134 @@ impl Debug for VirtD3 {
     }
 }
 
+/// Something that can be carried over to its equivalent under a [Symmetry].
+pub trait Transform {
+    fn transformed(&self, symmetry: Symmetry) -> Self;
+}
+
+/// One of the 48 rotations and reflections of the FCC lattice that leave the origin where it
+/// is: the real axes are permuted, then some of them are flipped.
+///
+/// Only the 12 that either flip every axis or none of them keep virtual lengths, so only
+/// those map a grid onto itself. See [Symmetry::preserves_grids].
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
+pub struct Symmetry {
+    // the input axis each output axis is taken from
+    permutation: [u8; 3],
+    // whether each output axis is flipped
+    flips: [bool; 3],
+}
+
+impl Symmetry {
+    pub const IDENTITY: Symmetry = Symmetry {
+        permutation: [0, 1, 2],
+        flips: [false; 3],
+    };
+
+    /// Point inversion through the origin, which takes the default starting layout to
+    /// itself with the colors swapped.
+    pub const INVERSION: Symmetry = Symmetry {
+        permutation: [0, 1, 2],
+        flips: [true; 3],
+    };
+
+    pub const ALL: [Symmetry; 48] = all_symmetries();
+
+    /// Whether this maps every grid, whatever its radius, onto itself.
+    pub fn preserves_grids(self) -> bool {
+        self.flips == [false; 3] || self.flips == [true; 3]
+    }
+
+    /// This symmetry, followed by `other`.
+    pub fn then(self, other: Symmetry) -> Symmetry {
+        let mut rval = Self::IDENTITY;
+
+        for n in 0..3 {
+            let from = other.permutation[n] as usize;
+            rval.permutation[n] = self.permutation[from];
+            rval.flips[n] = other.flips[n] != self.flips[from];
+        }
+
+        rval
+    }
+
+    pub fn inverse(self) -> Symmetry {
+        let mut rval = Self::IDENTITY;
+
+        for n in 0..3 {
+            let to = self.permutation[n] as usize;
+            rval.permutation[to] = n as u8;
+            rval.flips[to] = self.flips[n];
+        }
+
+        rval
+    }
+}
+
+const fn all_symmetries() -> [Symmetry; 48] {
+    const PERMUTATIONS: [[u8; 3]; 6] = [
+        [0, 1, 2],
+        [0, 2, 1],
+        [1, 0, 2],
+        [1, 2, 0],
+        [2, 0, 1],
+        [2, 1, 0],
+    ];
+
+    let mut result = [Symmetry::IDENTITY; 48];
+
+    let mut n = 0;
+    while n < result.len() {
+        let flips = n % 8;
+        result[n] = Symmetry {
+            permutation: PERMUTATIONS[n / 8],
+            flips: [flips & 1 != 0, flips & 2 != 0, flips & 4 != 0],
+        };
+        n += 1;
+    }
+
+    result
+}
+
+impl Transform for Real {
+    fn transformed(&self, symmetry: Symmetry) -> Self {
+        let components = [self.x, self.y, self.z];
+        let [x, y, z] = [0, 1, 2].map(|n| {
+            let c = components[symmetry.permutation[n] as usize];
+            if symmetry.flips[n] {
+                -c
+            } else {
+                c
+            }
+        });
+
+        real(x, y, z)
+    }
+}
+
+impl Transform for VirtD3 {
+    /// # Panics
+    ///
+    /// Panics if the result is too big for a [VirtD3], which can only happen far outside any
+    /// grid and for symmetries that don't [preserve grids](Symmetry::preserves_grids).
+    fn transformed(&self, symmetry: Symmetry) -> Self {
+        // each real component leaves out the virtual one at the same index, so permuting
+        // one permutes the other the same way
+        if symmetry.preserves_grids() {
+            let components = [self.i, self.j, self.k];
+            let [i, j, k] = symmetry.permutation.map(|p| components[p as usize]);
+            let rval = virt_d3(i, j, k);
+
+            return if symmetry.flips[0] { -rval } else { rval };
+        }
+
+        Real::from(*self)
+            .transformed(symmetry)
+            .try_into()
+            .expect("lattice symmetries should map lattice points to lattice points")
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use rand::prelude::*;
@@ -343,4 +473,74 @@ mod tests {
             assert_eq!(random_virt, convirt);
         }
     }
+
+    #[rstest]
+    fn symmetries_are_distinct_isometries() {
+        let point = real(1, 2, 3);
+        let images: std::collections::HashSet<_> = Symmetry::ALL
+            .iter()
+            .map(|s| {
+                let image = point.transformed(*s);
+                assert_eq!(image.length_squared(), point.length_squared());
+                assert!(VirtD3::try_from(image).is_ok());
+                (image.x, image.y, image.z)
+            })
+            .collect();
+
+        assert_eq!(images.len(), Symmetry::ALL.len());
+    }
+
+    #[rstest]
+    fn grid_symmetries_keep_virtual_lengths() {
+        let virt = virt_d3(1, -2, 4);
+        let grid_symmetries: Vec<_> = Symmetry::ALL
+            .into_iter()
+            .filter(|s| s.preserves_grids())
+            .collect();
+
+        assert_eq!(grid_symmetries.len(), 12);
+        for s in Symmetry::ALL {
+            let length = virt.transformed(s).length_squared();
+            assert_eq!(
+                length == virt.length_squared(),
+                s.preserves_grids(),
+                "{s:?}"
+            );
+        }
+    }
+
+    #[rstest]
+    fn virt_and_real_transforms_agree() {
+        for s in Symmetry::ALL {
+            for _ in 0..10 {
+                let virt = virt_d3(
+                    thread_rng().gen_range(-10..=10),
+                    thread_rng().gen_range(-10..=10),
+                    thread_rng().gen_range(-10..=10),
+                );
+
+                assert_eq!(
+                    Real::from(virt.transformed(s)),
+                    Real::from(virt).transformed(s)
+                );
+            }
+        }
+    }
+
+    #[rstest]
+    fn symmetries_compose_and_invert() {
+        let point = real(1, 2, 3);
+
+        for a in Symmetry::ALL {
+            assert_eq!(a.then(a.inverse()), Symmetry::IDENTITY);
+            assert_eq!(a.inverse().then(a), Symmetry::IDENTITY);
+
+            for b in Symmetry::ALL {
+                assert_eq!(
+                    point.transformed(a.then(b)),
+                    point.transformed(a).transformed(b)
+                );
+            }
+        }
+    }
 }
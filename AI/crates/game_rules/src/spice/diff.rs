@@ -0,0 +1,276 @@
+//! Whole-board differences between two positions, for bringing a client up to date after a
+//! reconnect, undo or load without resending every space. Per-move changes are better
+//! described by [MoveEvent](super::MoveEvent)s.
+
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+use super::{coord::*, grid::*, moves::*, players::*, SpiceState};
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct SpaceChange {
+    pub at: VirtD3,
+    pub old: GridSpace,
+    pub new: GridSpace,
+}
+
+/// Every space that differs between two grids of the same radius, in
+/// [Grid::enumerate_vc] order.
+#[derive(Debug, PartialEq, Eq, Clone, Default, Serialize, Deserialize)]
+pub struct GridDiff {
+    pub changes: Vec<SpaceChange>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct StateDiff {
+    pub grid: GridDiff,
+    pub player: SpicePlayer,
+    pub move_count: u16,
+    pub max_moves: u16,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum DiffError {
+    /// The grids have different radii, so one can't be turned into the other space by space.
+    RadiusMismatch,
+    /// A change is for a space that isn't on the grid.
+    OutOfBounds(VirtD3),
+    /// More than one change is for the same space.
+    DuplicateChange(VirtD3),
+    /// A change would block the center, which the game should have ended before it could.
+    CenterBlocked,
+    /// The grid being patched isn't the one the diff was taken from.
+    Conflict {
+        at: VirtD3,
+        expected: GridSpace,
+        found: GridSpace,
+    },
+}
+
+impl GridDiff {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// The diff that undoes this one.
+    pub fn inverse(&self) -> Self {
+        Self {
+            changes: self
+                .changes
+                .iter()
+                .map(|c| SpaceChange {
+                    at: c.at,
+                    old: c.new.clone(),
+                    new: c.old.clone(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl Grid {
+    /// The changes that turn this grid into `other`.
+    pub fn diff(&self, other: &Grid) -> Result<GridDiff, DiffError> {
+        if self.radius() != other.radius() {
+            return Err(DiffError::RadiusMismatch);
+        }
+
+        // same radius, so both enumerate the same coordinates in the same order
+        let changes = self
+            .enumerate_vc()
+            .zip(other.enumerate_vc())
+            .filter(|((_, old), (_, new))| old != new)
+            .map(|((at, old), (_, new))| SpaceChange {
+                at,
+                old: old.clone(),
+                new: new.clone(),
+            })
+            .collect();
+
+        Ok(GridDiff { changes })
+    }
+
+    /// Applies `diff`, after checking that every change starts from what's on this grid,
+    /// that no space is changed twice, and that the center isn't blocked. Nothing is changed
+    /// if any of that doesn't hold.
+    pub fn patch(&mut self, diff: &GridDiff) -> Result<(), DiffError> {
+        let mut seen = HashSet::new();
+
+        for change in &diff.changes {
+            if !seen.insert(change.at) {
+                return Err(DiffError::DuplicateChange(change.at));
+            }
+
+            if change.at == virt_d3(0, 0, 0) && change.new == GridSpace::Blocked {
+                return Err(DiffError::CenterBlocked);
+            }
+
+            match self.get_vc(change.at) {
+                None => return Err(DiffError::OutOfBounds(change.at)),
+                Some(found) if *found != change.old => {
+                    return Err(DiffError::Conflict {
+                        at: change.at,
+                        expected: change.old.clone(),
+                        found: found.clone(),
+                    })
+                }
+                Some(_) => (),
+            }
+        }
+
+        for change in &diff.changes {
+            self.set_vc_unchecked(change.at, change.new.clone());
+        }
+
+        Ok(())
+    }
+}
+
+impl SpiceState {
+    /// The changes that turn this state into `other`.
+    pub fn diff(&self, other: &SpiceState) -> Result<StateDiff, DiffError> {
+        Ok(StateDiff {
+            grid: self.grid.diff(&other.grid)?,
+            player: other.player,
+            move_count: other.move_count,
+            max_moves: other.max_moves,
+        })
+    }
+
+    /// Applies `diff`, leaving this state unchanged if it doesn't apply cleanly.
+    pub fn patch(&mut self, diff: &StateDiff) -> Result<(), DiffError> {
+        self.grid.patch(&diff.grid)?;
+
+        self.player = diff.player;
+        self.move_count = diff.move_count;
+        self.max_moves = diff.max_moves;
+        self.move_cache = MoveCache::from_grid(&self.grid);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mcts::GameState;
+    use rstest::*;
+
+    use super::*;
+    use crate::spice::fixtures::*;
+
+    #[rstest]
+    fn identical_grids_have_no_diff() {
+        let grid = Grid::default();
+
+        assert!(grid.diff(&grid.clone()).unwrap().is_empty());
+    }
+
+    #[rstest]
+    fn diff_is_minimal() {
+        let state = SpiceState::initial_state();
+        let move_ = "3,3,3:DS".parse().unwrap();
+        let preview = state.preview_move(&move_).unwrap();
+        let after = state.apply_move(&move_);
+
+        let diff = state.diff(&after).unwrap();
+
+        // the source's line count, plus the line, whose last space becomes the new endpoint
+        assert_eq!(diff.grid.changes.len(), preview.path.len() + 1);
+        assert!(diff.grid.changes.iter().all(|c| c.old != c.new));
+        assert_eq!(diff.player, SpicePlayer::Red);
+        assert_eq!(diff.move_count, 1);
+    }
+
+    #[rstest]
+    fn patches_replay_a_game(small_state: SpiceState) {
+        let mut state = small_state;
+        let mut synced = state.clone();
+
+        while state.terminal_value(state.player).is_none() {
+            let move_ = state.available_moves().next().unwrap();
+            let after = state.apply_move(&move_);
+
+            synced.patch(&state.diff(&after).unwrap()).unwrap();
+            assert_eq!(synced.grid, after.grid);
+            assert_eq!(synced.to_string(), after.to_string());
+
+            state = after;
+        }
+    }
+
+    #[rstest]
+    fn inverse_undoes_a_diff() {
+        let before = SpiceState::initial_state();
+        let after = before.apply_move(&"3,3,3:DS".parse().unwrap());
+
+        let diff = before.grid.diff(&after.grid).unwrap();
+        let mut grid = after.grid.clone();
+        grid.patch(&diff.inverse()).unwrap();
+
+        assert_eq!(grid, before.grid);
+    }
+
+    #[rstest]
+    fn mismatched_patches_are_rejected() {
+        let before = SpiceState::initial_state();
+        let after = before.apply_move(&"3,3,3:DS".parse().unwrap());
+        let diff = before.diff(&after).unwrap();
+
+        // applying the same diff twice would draw the line over itself
+        let mut patched = after.clone();
+        let first = diff.grid.changes[0].clone();
+        assert_eq!(
+            patched.patch(&diff),
+            Err(DiffError::Conflict {
+                at: first.at,
+                expected: first.old,
+                found: first.new,
+            })
+        );
+        assert_eq!(patched, after);
+
+        let mut small = Grid::with_radius(2.);
+        assert_eq!(small.diff(&before.grid), Err(DiffError::RadiusMismatch));
+
+        let far = GridDiff {
+            changes: vec![SpaceChange {
+                at: virt_d3(3, 3, 3),
+                old: GridSpace::Empty,
+                new: GridSpace::Blocked,
+            }],
+        };
+        assert_eq!(
+            small.patch(&far),
+            Err(DiffError::OutOfBounds(virt_d3(3, 3, 3)))
+        );
+    }
+
+    #[rstest]
+    fn broken_patches_are_rejected() {
+        let change = |at, new| SpaceChange {
+            at,
+            old: GridSpace::Empty,
+            new,
+        };
+        let mut grid = Grid::default();
+
+        let twice = GridDiff {
+            changes: vec![
+                change(virt_d3(1, 1, 1), GridSpace::Blocked),
+                change(virt_d3(1, 1, 1), GridSpace::Empty),
+            ],
+        };
+        assert_eq!(
+            grid.patch(&twice),
+            Err(DiffError::DuplicateChange(virt_d3(1, 1, 1)))
+        );
+
+        let center = GridDiff {
+            changes: vec![change(virt_d3(0, 0, 0), GridSpace::Blocked)],
+        };
+        assert_eq!(grid.patch(&center), Err(DiffError::CenterBlocked));
+
+        assert_eq!(grid, Grid::default());
+    }
+}
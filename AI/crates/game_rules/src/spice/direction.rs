@@ -1,8 +1,10 @@
 use std::ops::*;
 
+use serde::{Deserialize, Serialize};
+
 use super::coord::*;
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
 pub enum Direction {
     NorthEast,
     NorthWest,
@@ -18,7 +20,7 @@ pub enum Direction {
     DownWest,
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
 pub enum Axis {
     NeSw,
     NwSe,
@@ -57,6 +59,15 @@ impl Direction {
 }
 
 impl Axis {
+    pub const ALL: [Axis; 6] = [
+        Axis::NeSw,
+        Axis::NwSe,
+        Axis::UnDs,
+        Axis::UsDn,
+        Axis::UeDw,
+        Axis::UwDe,
+    ];
+
     pub fn directions(self) -> (Direction, Direction) {
         match self {
             Axis::NeSw => (Direction::NorthEast, Direction::SouthWest),
@@ -197,6 +208,23 @@ impl SubAssign<Direction> for VirtD3 {
     }
 }
 
+impl Transform for Direction {
+    fn transformed(&self, symmetry: Symmetry) -> Self {
+        let vector = VirtD3::from(*self).transformed(symmetry);
+
+        Direction::ALL
+            .into_iter()
+            .find(|&d| VirtD3::from(d) == vector)
+            .expect("lattice symmetries should map neighbors to neighbors")
+    }
+}
+
+impl Transform for Axis {
+    fn transformed(&self, symmetry: Symmetry) -> Self {
+        self.directions().0.transformed(symmetry).axis()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use rand::prelude::*;
@@ -254,4 +282,17 @@ mod tests {
             assert_eq!(-dir1, dir2);
         }
     }
+
+    #[rstest]
+    fn symmetries_permute_directions() {
+        for s in Symmetry::ALL {
+            let images: Vec<_> = Direction::ALL.map(|d| d.transformed(s)).into();
+            assert!(Direction::ALL.iter().all(|d| images.contains(d)), "{s:?}");
+
+            for dir in Direction::ALL {
+                assert_eq!((-dir).transformed(s), -dir.transformed(s));
+                assert_eq!(dir.transformed(s).axis(), dir.axis().transformed(s));
+            }
+        }
+    }
 }
@@ -0,0 +1,256 @@
+//! Linear evaluation of Spice positions, over a handful of hand-picked features. Weights
+//! are learned offline (see [super::train_td]) and stored as JSON.
+
+use std::{fs, io, path::Path};
+
+use mcts::{Evaluator, GameState};
+use serde::{Deserialize, Serialize};
+
+use super::{coord::*, direction::*, grid::*, moves::*, players::*, SpiceState};
+
+pub const FEATURE_COUNT: usize = 9;
+
+/// Names for each entry of [features], in order. Anything labelled "mine" is about the
+/// player the position is being evaluated for.
+pub const FEATURE_NAMES: [&str; FEATURE_COUNT] = [
+    "endpoints (mine)",
+    "endpoints (theirs)",
+    "center proximity (mine)",
+    "center proximity (theirs)",
+    "hardened lines (mine)",
+    "hardened lines (theirs)",
+    "mobility (mine)",
+    "mobility (theirs)",
+    "to move",
+];
+
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
+pub struct LinearEvaluator {
+    pub weights: [f32; FEATURE_COUNT],
+}
+
+impl LinearEvaluator {
+    /// Estimated value of `state` for `for_player`, between -1 and 1.
+    pub fn value(&self, state: &SpiceState, for_player: SpicePlayer) -> f32 {
+        self.value_of_features(&features(state, for_player))
+    }
+
+    pub fn value_of_features(&self, features: &[f32; FEATURE_COUNT]) -> f32 {
+        self.weights
+            .iter()
+            .zip(features)
+            .map(|(w, x)| w * x)
+            .sum::<f32>()
+            .tanh()
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(serde_json::from_slice(&fs::read(path)?)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, serde_json::to_vec_pretty(self)?)
+    }
+}
+
+impl Evaluator<SpiceState> for LinearEvaluator {
+    fn evaluate(&self, state: &SpiceState, for_player: SpicePlayer) -> f32 {
+        self.value(state, for_player)
+    }
+}
+
+/// Feature vector for `state` from the perspective of `for_player`, with every entry
+/// scaled to be between 0 and 1.
+pub fn features(state: &SpiceState, for_player: SpicePlayer) -> [f32; FEATURE_COUNT] {
+    let mine = PlayerFeatures::new(state, for_player);
+    let theirs = PlayerFeatures::new(state, for_player.opponent());
+    let to_move = if state.next_to_play() == for_player {
+        1.
+    } else {
+        0.
+    };
+
+    [
+        mine.endpoints,
+        theirs.endpoints,
+        mine.center_proximity,
+        theirs.center_proximity,
+        mine.hardened_lines,
+        theirs.hardened_lines,
+        mine.mobility,
+        theirs.mobility,
+        to_move,
+    ]
+}
+
+struct PlayerFeatures {
+    endpoints: f32,
+    center_proximity: f32,
+    hardened_lines: f32,
+    mobility: f32,
+}
+
+impl PlayerFeatures {
+    fn new(state: &SpiceState, player: SpicePlayer) -> Self {
+        let coords = state.move_cache.endpoint_coords(player);
+
+        // no space is this far from the center, whatever the board's exact radius
+        let bound = state.grid.extent() as f32 + 1.;
+        let closest = coords.iter().map(|c| c.length()).fold(bound, f32::min);
+
+        // every line runs between two of its owner's endpoints, so walking out along the
+        // lines from each endpoint finds every hardened space on them twice
+        let hardened_lines = coords
+            .iter()
+            .flat_map(|&c| Direction::ALL.map(|d| hardened_along_line(&state.grid, c, d)))
+            .sum::<usize>()
+            / 2;
+
+        let mobility = state.move_cache.move_count(player);
+        let spaces = state.grid.space_count() as f32;
+
+        Self {
+            endpoints: coords.len() as f32 / spaces,
+            center_proximity: 1. - closest / bound,
+            hardened_lines: hardened_lines as f32 / spaces,
+            // each endpoint can move at most once in every direction
+            mobility: if coords.is_empty() {
+                0.
+            } else {
+                mobility as f32 / (coords.len() * Direction::ALL.len()) as f32
+            },
+        }
+    }
+}
+
+/// How many hardened spaces are on the line leaving the endpoint at `from` in `direction`,
+/// if there is one.
+fn hardened_along_line(grid: &Grid, from: VirtD3, direction: Direction) -> usize {
+    let axis = direction.axis();
+
+    grid.ray(from, direction)
+        .map(|spot| grid.get_spot(spot))
+        .take_while(|s| matches!(s, GridSpace::LineSegment { axis: a, .. } if *a == axis))
+        .filter(|s| matches!(s, GridSpace::LineSegment { hardened: true, .. }))
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use mcts::{SearchParameters, Searcher};
+    use rstest::*;
+
+    use super::*;
+    use crate::spice::{fixtures::*, positions::*};
+
+    #[fixture]
+    fn evaluator() -> LinearEvaluator {
+        LinearEvaluator {
+            weights: [0.5, -0.5, 1., -1., -0.2, 0.2, 0.3, -0.3, 0.1],
+        }
+    }
+
+    #[rstest]
+    fn initial_features_are_symmetric() {
+        let state = SpiceState::initial_state();
+
+        let blue = features(&state, SpicePlayer::Blue);
+        let red = features(&state, SpicePlayer::Red);
+
+        assert_eq!(blue[0..8], red[0..8]);
+        assert_eq!(blue[8], 1.);
+        assert_eq!(red[8], 0.);
+    }
+
+    #[rstest]
+    fn features_are_scaled(small_state: SpiceState) {
+        let parameters = PositionParameters {
+            target: PositionTarget::MoveCount(200),
+            policy: MovePolicy::Random,
+            filters: vec![],
+            max_attempts: 1,
+        };
+        let later = PositionGenerator::new(parameters, 0).take(5);
+
+        for state in [SpiceState::initial_state(), small_state]
+            .into_iter()
+            .chain(later)
+        {
+            for player in [SpicePlayer::Blue, SpicePlayer::Red] {
+                for x in features(&state, player) {
+                    assert!((0. ..=1.).contains(&x), "{x} is out of range in {state}");
+                }
+            }
+        }
+    }
+
+    #[rstest]
+    fn hardened_lines_are_counted_for_their_owner() {
+        let line: Vec<_> = Grid::default()
+            .ray(virt_d3(0, 0, 0), Direction::UpNorth)
+            .take(4)
+            .map(|s| s.coord)
+            .collect();
+        let endpoint = |owner| GridSpace::Endpoint {
+            owner,
+            connected_lines: 1,
+        };
+        let segment = |hardened| GridSpace::LineSegment {
+            axis: Direction::UpNorth.axis(),
+            hardened,
+        };
+
+        let state = blocked_state(&[
+            (virt_d3(0, 0, 0), endpoint(SpicePlayer::Blue)),
+            (line[0], segment(true)),
+            (line[1], segment(false)),
+            (line[2], segment(true)),
+            (line[3], endpoint(SpicePlayer::Blue)),
+            (virt_d3(-2, -2, 0), endpoint(SpicePlayer::Red)),
+        ]);
+        let spaces = state.grid.space_count() as f32;
+
+        let blue = features(&state, SpicePlayer::Blue);
+        let red = features(&state, SpicePlayer::Red);
+
+        assert_eq!(blue[4], 2. / spaces);
+        assert_eq!(blue[5], 0.);
+        assert_eq!(red[4], 0.);
+        assert_eq!(red[5], 2. / spaces);
+    }
+
+    #[rstest]
+    fn value_is_bounded(evaluator: LinearEvaluator, small_state: SpiceState) {
+        let value = evaluator.value(&small_state, SpicePlayer::Red);
+        assert!((-1. ..=1.).contains(&value));
+    }
+
+    #[rstest]
+    fn save_load_round_trip(evaluator: LinearEvaluator) {
+        let path = std::env::temp_dir().join(format!(
+            "spice_linear_evaluator_{}.json",
+            std::process::id()
+        ));
+
+        evaluator.save(&path).unwrap();
+        let loaded = LinearEvaluator::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded, evaluator);
+    }
+
+    #[rstest]
+    fn searcher_can_cut_off_rollouts(evaluator: LinearEvaluator) {
+        let mut searcher = Searcher::with_evaluator(
+            SearchParameters {
+                exploration_factor: std::f32::consts::FRAC_1_SQRT_2,
+                search_iterations: 10,
+                solver_node_budget: None,
+                rollout_cutoff: Some(4),
+            },
+            evaluator,
+        );
+
+        searcher.search(SpiceState::initial_state());
+    }
+}
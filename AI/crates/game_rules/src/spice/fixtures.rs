@@ -0,0 +1,100 @@
+//! rstest fixtures shared between the tests of different Spice modules.
+
+use mcts::GameState;
+use rstest::*;
+
+use super::{config::*, coord::*, grid::*, moves::*, players::*, SpiceState};
+
+/// A tiny arena around the center, so that whole games finish quickly.
+#[fixture]
+pub fn small_state() -> SpiceState {
+    let mut grid = Grid::default();
+
+    let coords: Vec<VirtD3> = grid.enumerate_vc().map(|(c, _)| c).collect();
+    for c in coords {
+        if c.length_squared() > 2. {
+            grid.set_vc_unchecked(c, GridSpace::Blocked);
+        }
+    }
+
+    grid.set_vc_unchecked(
+        virt_d3(1, 1, 0),
+        GridSpace::Endpoint {
+            owner: SpicePlayer::Blue,
+            connected_lines: 0,
+        },
+    );
+    grid.set_vc_unchecked(
+        virt_d3(-1, -1, 0),
+        GridSpace::Endpoint {
+            owner: SpicePlayer::Red,
+            connected_lines: 0,
+        },
+    );
+
+    let move_cache = MoveCache::from_grid(&grid);
+    SpiceState {
+        grid,
+        player: SpicePlayer::Blue,
+        move_cache,
+        move_count: 0,
+        max_moves: DEFAULT_MAX_MOVES,
+    }
+}
+
+/// Blue to move on a grid where every space is blocked except `spaces`.
+pub fn blocked_state(spaces: &[(VirtD3, GridSpace)]) -> SpiceState {
+    let mut grid = Grid::default();
+
+    let coords: Vec<VirtD3> = grid.enumerate_vc().map(|(c, _)| c).collect();
+    for c in coords {
+        grid.set_vc_unchecked(c, GridSpace::Blocked);
+    }
+
+    for (c, s) in spaces {
+        grid.set_vc_unchecked(*c, s.clone());
+    }
+
+    let move_cache = MoveCache::from_grid(&grid);
+    SpiceState {
+        grid,
+        player: SpicePlayer::Blue,
+        move_cache,
+        move_count: 0,
+        max_moves: DEFAULT_MAX_MOVES,
+    }
+}
+
+/// Red can take the center next turn unless Blue moves from (1, -2, 0) to (2, -2, 0) to get
+/// in the way, which leaves Red with no moves and the game drawn. Blue's only other move
+/// loses.
+#[fixture]
+pub fn blockable_center_state() -> SpiceState {
+    let endpoint = |owner| GridSpace::Endpoint {
+        owner,
+        connected_lines: 0,
+    };
+
+    blocked_state(&[
+        (virt_d3(1, -2, 0), endpoint(SpicePlayer::Blue)),
+        (virt_d3(2, -2, 0), GridSpace::Empty),
+        (virt_d3(0, -2, 0), endpoint(SpicePlayer::Red)),
+        (virt_d3(0, -1, 0), GridSpace::Empty),
+        (virt_d3(0, 0, 0), GridSpace::Empty),
+    ])
+}
+
+/// The first `count` moves of the game from `state`, always playing the first legal move,
+/// stopping early if the game ends.
+pub fn first_moves(state: &SpiceState, count: usize) -> Vec<SpiceMove> {
+    let mut state = state.clone();
+    let mut moves = Vec::new();
+
+    while moves.len() < count && state.terminal_value(state.player).is_none() {
+        let move_ = state.available_moves().next().unwrap();
+        state = state.apply_move(&move_);
+        moves.push(move_);
+    }
+
+    moves
+}
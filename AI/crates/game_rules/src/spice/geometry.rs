@@ -0,0 +1,168 @@
+//! Geometry of the FCC lattice itself, without a grid: distances, straight lines and
+//! neighborhoods around [VirtD3]s.
+
+use super::{coord::*, direction::*};
+
+impl VirtD3 {
+    /// The fewest steps between neighboring points it takes to get from here to `other`.
+    pub fn distance(self, other: VirtD3) -> u16 {
+        let [i, j, k] = [
+            other.i as i16 - self.i as i16,
+            other.j as i16 - self.j as i16,
+            other.k as i16 - self.k as i16,
+        ];
+
+        // with a fourth component of -(i + j + k), every step adds one to a component and
+        // takes one from another
+        ((i.abs() + j.abs() + k.abs() + (i + j + k).abs()) / 2) as u16
+    }
+
+    /// The direction to go in from here to reach `other` in a straight line, if there is one.
+    pub fn direction_to(self, other: VirtD3) -> Option<Direction> {
+        let delta = [
+            other.i as i16 - self.i as i16,
+            other.j as i16 - self.j as i16,
+            other.k as i16 - self.k as i16,
+        ];
+
+        Direction::ALL.into_iter().find(|&d| {
+            let VirtD3 { i, j, k } = d.into();
+            let step = [i as i16, j as i16, k as i16];
+
+            // the distance is how many steps it would take along a line
+            let steps = self.distance(other) as i16;
+            steps > 0 && (0..3).all(|n| step[n] * steps == delta[n])
+        })
+    }
+
+    /// The axis of the line through here and `other`, if they're on one.
+    pub fn axis_to(self, other: VirtD3) -> Option<Axis> {
+        self.direction_to(other).map(Direction::axis)
+    }
+
+    /// Every point from here in `direction`, in order, for as long as they'd be on a grid
+    /// of `radius`. Doesn't include this point.
+    pub fn ray(self, direction: Direction, radius: f32) -> impl Iterator<Item = VirtD3> {
+        let step = VirtD3::from(direction);
+
+        std::iter::successors(Some(self), move |&c| c.checked_add(step))
+            .skip(1)
+            .take_while(move |c| c.length_squared() < radius * radius)
+    }
+
+    /// Every point at most `distance` steps from here, other than this one, in
+    /// [Grid::enumerate_vc](super::Grid::enumerate_vc) order.
+    pub fn neighbors_within(self, distance: u8) -> impl Iterator<Item = VirtD3> {
+        let n = distance as i16;
+        let offsets = move || -n..=n;
+
+        // no component can change by more than the distance
+        offsets()
+            .flat_map(move |i| offsets().flat_map(move |j| offsets().map(move |k| (i, j, k))))
+            .filter_map(move |(i, j, k)| {
+                let c = |base: i8, offset: i16| i8::try_from(base as i16 + offset).ok();
+                Some(virt_d3(c(self.i, i)?, c(self.j, j)?, c(self.k, k)?))
+            })
+            .filter(move |&c| c != self && self.distance(c) <= distance as u16)
+    }
+
+    fn checked_add(self, other: VirtD3) -> Option<VirtD3> {
+        Some(virt_d3(
+            self.i.checked_add(other.i)?,
+            self.j.checked_add(other.j)?,
+            self.k.checked_add(other.k)?,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, VecDeque};
+
+    use rstest::*;
+
+    use super::*;
+    use crate::spice::grid::*;
+
+    #[rstest]
+    fn distance_matches_a_search() {
+        let origin = virt_d3(0, 0, 0);
+        let mut distances = HashMap::from([(origin, 0)]);
+        let mut queue = VecDeque::from([origin]);
+
+        while let Some(c) = queue.pop_front() {
+            let d = distances[&c];
+            if d == 4 {
+                continue;
+            }
+
+            for dir in Direction::ALL {
+                distances.entry(c + dir).or_insert_with(|| {
+                    queue.push_back(c + dir);
+                    d + 1
+                });
+            }
+        }
+
+        for (c, d) in distances {
+            assert_eq!(origin.distance(c), d, "{c}");
+            assert_eq!(c.distance(origin), d, "{c}");
+        }
+    }
+
+    #[rstest]
+    #[case(virt_d3(0, 0, 0), virt_d3(3, 0, 0), Some(Direction::UpNorth))]
+    #[case(virt_d3(1, 1, 1), virt_d3(-1, 3, 1), Some(Direction::SouthEast))]
+    #[case(virt_d3(1, 1, 1), virt_d3(2, 2, 1), None)]
+    #[case(virt_d3(1, 1, 1), virt_d3(1, 1, 1), None)]
+    fn directions_between_points(
+        #[case] from: VirtD3,
+        #[case] to: VirtD3,
+        #[case] expected: Option<Direction>,
+    ) {
+        assert_eq!(from.direction_to(to), expected);
+        assert_eq!(to.direction_to(from), expected.map(|d| -d));
+        assert_eq!(from.axis_to(to), expected.map(Direction::axis));
+    }
+
+    #[rstest]
+    fn rays_match_the_grid() {
+        let grid = Grid::default();
+
+        for (c, _) in grid.enumerate_vc() {
+            for dir in Direction::ALL {
+                let from_grid: Vec<_> = grid.ray(c, dir).map(|s| s.coord).collect();
+                let ray: Vec<_> = c.ray(dir, grid.radius()).collect();
+
+                assert_eq!(ray, from_grid, "{c} {dir:?}");
+                for (n, point) in ray.iter().enumerate() {
+                    assert_eq!(c.direction_to(*point), Some(dir));
+                    assert_eq!(c.distance(*point), n as u16 + 1);
+                }
+            }
+        }
+    }
+
+    #[rstest]
+    #[case(0, 0)]
+    #[case(1, 12)]
+    #[case(2, 54)]
+    #[case(3, 146)]
+    fn neighborhoods_have_the_right_size(#[case] distance: u8, #[case] expected: usize) {
+        let center = virt_d3(2, -1, 0);
+        let neighbors: Vec<_> = center.neighbors_within(distance).collect();
+
+        assert_eq!(neighbors.len(), expected);
+        assert!(neighbors
+            .iter()
+            .all(|&c| (1..=distance as u16).contains(&center.distance(c))));
+    }
+
+    #[rstest]
+    fn neighborhoods_stop_at_the_edge_of_the_coordinates() {
+        let corner = virt_d3(i8::MAX, i8::MAX, i8::MAX);
+
+        assert!(corner.neighbors_within(2).all(|c| c.distance(corner) <= 2));
+        assert_eq!(corner.ray(Direction::UpNorth, f32::INFINITY).count(), 0);
+    }
+}
@@ -1,17 +1,91 @@
-use ndarray::Array3;
+use std::{
+    fmt,
+    hash::{Hash, Hasher},
+    iter,
+    sync::{Arc, Mutex},
+};
 
-use super::{coord::*, direction::*, players::*};
+use serde::{Deserialize, Serialize};
 
+use super::{config::*, coord::*, direction::*, players::*, serialization::*, zobrist::*};
+
+// the radius of the default board
 pub const GRID_CONSTANT_F: f32 = 5.2;
 pub const GRID_CONSTANT_I: i8 = 5; // GRID_CONSTANT_F.floor(), hardcoded bc floor() isn't const
 
-#[derive(Debug, PartialEq, Clone)]
+// the planes of a grid's bitboard. each is the set of cells that have some property
+const EMPTY: usize = 0;
+const BLOCKED: usize = 1;
+const HARDENED: usize = 2;
+// one plane per player, offset by `player as usize`
+const ENDPOINTS: usize = 3;
+// one plane per axis, offset by `axis as usize`
+const LINES: usize = 5;
+// an endpoint's connected_lines, one plane per bit, least significant first
+const CONNECTIONS: usize = 11;
+const CONNECTION_BITS: usize = u8::BITS as usize;
+const PLANES: usize = CONNECTIONS + CONNECTION_BITS;
+
+// ends every ray in a shape's ray table
+const RAY_END: u32 = u32::MAX;
+
+// shapes are expensive to build, so the most recently used are kept for new grids to share
+const CACHED_SHAPES: usize = 8;
+static SHAPES: Mutex<Vec<Arc<Shape>>> = Mutex::new(Vec::new());
+
+#[derive(PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[serde(into = "GridRepr", try_from = "GridRepr")]
 pub struct Grid {
-    // spaces are indexed by VirtD3s, offset by a constant factor
-    packed_spaces: Array3<Option<GridSpace>>,
+    // kept up to date as spaces are written. first, so that comparing different grids
+    // usually stops here
+    zobrist: u64,
+    // a bitboard, with each word of every plane next to the same word of the others, so
+    // that everything about a cell is in one place. cells are numbered by their VirtD3,
+    // offset by the extent, in (i, j, k) order
+    words: Vec<u64>,
+    // everything that only depends on the radius, shared between clones
+    shape: Arc<Shape>,
+}
+
+#[derive(Debug)]
+struct Shape {
+    // the largest coordinate component of any space in the sphere
+    extent: i8,
+    // cells along each axis of the cube around the sphere
+    width: usize,
+    // the bits of the f32 radius, so that grids can still be Eq and Hash
+    radius: u32,
+    // one bit per cell, set for the cells that are part of the grid
+    in_sphere: Vec<u64>,
+    // the coordinate of every cell, to save dividing to find them
+    coords: Vec<VirtD3>,
+    // for each direction, the cells in the sphere whose neighbor that way is also in it
+    has_neighbor: [Vec<u64>; 12],
+    // every line of cells across the sphere, once in each direction, each followed by
+    // RAY_END. the ray from a cell in some direction is the rest of its line that way
+    rays: Vec<u32>,
+    // for every cell and direction, where in `rays` its ray starts
+    ray_starts: Vec<[u32; 12]>,
+}
+
+/// A space's coordinate, along with where its grid keeps it, so that it can be found again
+/// without any coordinate math. Only meaningful for grids with the same radius as the one
+/// it came from.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct Spot {
+    pub coord: VirtD3,
+    cell: u32,
+}
+
+/// The spaces in a line from some space to the edge of the grid, not including the space
+/// itself. Made by [Grid::ray].
+#[derive(Debug, Clone)]
+pub struct Ray {
+    shape: Arc<Shape>,
+    next: usize,
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Serialize, Deserialize)]
 pub enum GridSpace {
     Empty,
     Blocked,
@@ -25,35 +99,202 @@ pub enum GridSpace {
     },
 }
 
+// the grid doesn't store GridSpaces, so every one it could return a reference to lives here
+static LINE_SPACES: [[GridSpace; 2]; 6] = line_spaces();
+static ENDPOINT_SPACES: [[GridSpace; 1 << CONNECTION_BITS]; 2] = [
+    endpoint_spaces(SpicePlayer::Red),
+    endpoint_spaces(SpicePlayer::Blue),
+];
+
+const fn line_spaces() -> [[GridSpace; 2]; 6] {
+    const UNSET: [GridSpace; 2] = [GridSpace::Empty, GridSpace::Empty];
+    let mut result = [UNSET; 6];
+
+    let mut a = 0;
+    while a < Axis::ALL.len() {
+        let axis = Axis::ALL[a];
+        result[axis as usize] = [
+            GridSpace::LineSegment {
+                axis,
+                hardened: false,
+            },
+            GridSpace::LineSegment {
+                axis,
+                hardened: true,
+            },
+        ];
+        a += 1;
+    }
+
+    result
+}
+
+const fn endpoint_spaces(owner: SpicePlayer) -> [GridSpace; 1 << CONNECTION_BITS] {
+    const UNSET: GridSpace = GridSpace::Empty;
+    let mut result = [UNSET; 1 << CONNECTION_BITS];
+
+    let mut n = 0;
+    while n < result.len() {
+        result[n] = GridSpace::Endpoint {
+            owner,
+            connected_lines: n as u8,
+        };
+        n += 1;
+    }
+
+    result
+}
+
+// everything else about a shape follows from its radius
+impl PartialEq for Shape {
+    fn eq(&self, other: &Self) -> bool {
+        self.radius == other.radius
+    }
+}
+
+impl Eq for Shape {}
+
+impl Hash for Shape {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.radius.hash(state);
+    }
+}
+
+// equal grids have equal hashes, so that's all that needs hashing
+impl Hash for Grid {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.zobrist.hash(state);
+    }
+}
+
 impl Default for Grid {
     fn default() -> Self {
-        let grid_width = Self::axis_length();
+        Self::with_radius(GRID_CONSTANT_F)
+    }
+}
+
+impl fmt::Debug for Grid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let spaces: Vec<_> = self
+            .enumerate_vc()
+            .filter(|(_, s)| **s != GridSpace::Empty)
+            .collect();
 
-        let mut result = Self {
-            packed_spaces: Array3::from_elem((grid_width, grid_width, grid_width), None),
+        f.debug_struct("Grid")
+            .field("radius", &self.radius())
+            .field("spaces", &spaces)
+            .finish()
+    }
+}
+
+impl Grid {
+    /// An empty grid of every space with a virtual length less than `radius`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `radius` isn't between 1 and [MAX_RADIUS].
+    pub fn with_radius(radius: f32) -> Self {
+        Self::try_with_radius(radius)
+            .unwrap_or_else(|| panic!("{radius} is not a valid grid radius"))
+    }
+
+    /// Like [Grid::with_radius], but returns [None] if `radius` isn't between 1 and
+    /// [MAX_RADIUS]. Every other way of making a grid goes through this, so they all agree
+    /// on which radii are allowed.
+    pub fn try_with_radius(radius: f32) -> Option<Self> {
+        if !(1. ..=MAX_RADIUS).contains(&radius) {
+            return None;
+        }
+
+        let shape = Shape::cached(radius);
+        let word_count = shape.in_sphere.len();
+
+        let mut words = vec![0; word_count * PLANES];
+        for (w, &mask) in shape.in_sphere.iter().enumerate() {
+            words[w * PLANES + EMPTY] = mask;
+        }
+
+        // empty spaces are left out of the hash, so an empty grid hashes to 0
+        Some(Self {
+            zobrist: 0,
+            words,
+            shape,
+        })
+    }
+
+    /// Builds the starting grid for a game with the layout in `config`.
+    pub fn from_config(config: &SpiceConfig) -> Result<Self, ConfigError> {
+        let mut grid = Self::try_with_radius(config.radius)
+            .ok_or(ConfigError::InvalidRadius(config.radius))?;
+
+        for player in [SpicePlayer::Blue, SpicePlayer::Red] {
+            if config.endpoints(player).is_empty() {
+                return Err(ConfigError::NoEndpoints(player));
+            }
+        }
+
+        let endpoint = |owner| GridSpace::Endpoint {
+            owner,
+            connected_lines: 0,
         };
+        let placements = config
+            .blue_endpoints
+            .iter()
+            .map(|&c| (c, endpoint(SpicePlayer::Blue)))
+            .chain(
+                config
+                    .red_endpoints
+                    .iter()
+                    .map(|&c| (c, endpoint(SpicePlayer::Red))),
+            )
+            .chain(config.blocked.iter().map(|&c| (c, GridSpace::Blocked)));
 
-        for i in -GRID_CONSTANT_I..=GRID_CONSTANT_I {
-            for j in -GRID_CONSTANT_I..=GRID_CONSTANT_I {
-                for k in -GRID_CONSTANT_I..=GRID_CONSTANT_I {
-                    let virt = virt_d3(i, j, k);
+        for (coord, space) in placements {
+            if coord == virt_d3(0, 0, 0) {
+                return Err(ConfigError::CenterOccupied);
+            }
 
-                    if Self::in_sphere(virt) {
-                        result.packed_spaces[Self::indexify(virt)] = Some(GridSpace::Empty);
-                    }
-                }
+            match grid.get_vc(coord) {
+                None => return Err(ConfigError::OutOfBounds(coord)),
+                Some(GridSpace::Empty) => grid.set_vc_unchecked(coord, space),
+                Some(_) => return Err(ConfigError::Overlapping(coord)),
             }
         }
 
-        result
+        Ok(grid)
+    }
+
+    /// A hash of every space, kept up to date as they change. Equal grids always have the
+    /// same hash; different ones almost never do.
+    pub fn zobrist_hash(&self) -> u64 {
+        self.zobrist
+    }
+
+    /// The radius this grid was created with.
+    pub fn radius(&self) -> f32 {
+        f32::from_bits(self.shape.radius)
+    }
+
+    /// The largest value any component of a space's coordinate can have.
+    pub fn extent(&self) -> i8 {
+        self.shape.extent
+    }
+
+    /// How many spaces are in the grid, whatever is in them.
+    pub fn space_count(&self) -> usize {
+        self.shape
+            .in_sphere
+            .iter()
+            .map(|w| w.count_ones() as usize)
+            .sum()
     }
-}
 
-impl Grid {
     pub fn enumerate_vc(&self) -> impl Iterator<Item = (VirtD3, &GridSpace)> {
-        self.packed_spaces
-            .indexed_iter()
-            .filter_map(|(c, s)| s.as_ref().map(|space| (Self::unindexify(c), space)))
+        let width = self.shape.width;
+
+        (0..width * width * width)
+            .filter(|&cell| self.shape.contains(cell))
+            .map(|cell| (self.shape.uncell(cell), self.read_cell(cell)))
     }
 
     /// Attempt to retrieve a space in the grid, using a [Real] coordinate.
@@ -66,13 +307,9 @@ impl Grid {
 
     /// Attempt to retrieve a space in the grid, using a [VirtD3] coordinate.
     pub fn get_vc(&self, index: VirtD3) -> Option<&GridSpace> {
-        let idx = Self::indexify(index);
-        self.packed_spaces.get(idx).and_then(|s| s.as_ref())
-    }
-
-    pub fn get_mut_vc(&mut self, index: VirtD3) -> Option<&mut GridSpace> {
-        let idx = Self::indexify(index);
-        self.packed_spaces.get_mut(idx).and_then(|s| s.as_mut())
+        self.shape
+            .sphere_cell(index)
+            .map(|cell| self.read_cell(cell))
     }
 
     /// Attempt to set a space in the grid, using a [Real] coordinate.
@@ -89,27 +326,20 @@ impl Grid {
 
     /// Attempt to set a space in the grid, using a [VirtD3] coordinate.
     pub fn set_vc(&mut self, index: VirtD3, value: GridSpace) -> Result<(), String> {
-        if !Self::in_sphere(index) {
-            return Err(format!("{index:?} is out of sphere bounds"));
-        }
-
-        let idx = Self::indexify(index);
-
-        let (i, j, k) = idx;
-        if i > Self::axis_length() || j > Self::axis_length() || k > Self::axis_length() {
-            return Err(format!("{idx:?} is out of array bounds"));
+        match self.shape.sphere_cell(index) {
+            None => Err(format!("{index:?} is out of sphere bounds")),
+            Some(cell) => {
+                self.write_cell(cell, &value);
+                Ok(())
+            }
         }
-
-        self.packed_spaces[idx] = Some(value);
-
-        Ok(())
     }
 
     /// Set a space in the grid, using a [Real] coordinate.
     ///
     /// # Panics
     ///
-    /// Panics if the coordinate isn't in D3, or is out of the bounds of the array.
+    /// Panics if the coordinate isn't in D3, or is outside the sphere.
     pub fn set_rc_unchecked(&mut self, index: Real, value: GridSpace) {
         self.set_vc_unchecked(index.try_into().unwrap(), value);
     }
@@ -118,27 +348,138 @@ impl Grid {
     ///
     /// # Panics
     ///
-    /// Panics if the coordinate is out of the bounds of the array.
+    /// Panics if the coordinate is outside the sphere.
     pub fn set_vc_unchecked(&mut self, index: VirtD3, value: GridSpace) {
-        self.packed_spaces[Self::indexify(index)] = Some(value);
+        let cell = self
+            .shape
+            .sphere_cell(index)
+            .unwrap_or_else(|| panic!("{index:?} is out of sphere bounds"));
+
+        self.write_cell(cell, &value);
     }
 
     pub fn is_valid_and_empty_vc(&self, index: VirtD3) -> bool {
-        matches!(
-            self.packed_spaces.get(Self::indexify(index)),
-            Some(Some(GridSpace::Empty))
-        )
+        // only spaces in the sphere are ever in the empty plane
+        self.shape
+            .cube_cell(index)
+            .is_some_and(|cell| self.has(cell, EMPTY))
     }
 
-    pub fn center_owner(&self) -> Option<SpicePlayer> {
-        const CENTER: [usize; 3] = [
-            GRID_CONSTANT_I as usize,
-            GRID_CONSTANT_I as usize,
-            GRID_CONSTANT_I as usize,
-        ];
+    /// Every endpoint at or next to `index`, with its owner.
+    pub fn endpoints_around_vc(
+        &self,
+        index: VirtD3,
+    ) -> impl Iterator<Item = (VirtD3, SpicePlayer)> + '_ {
+        let cells = self.shape.sphere_cell(index).into_iter().flat_map(|cell| {
+            iter::once(cell).chain(self.neighbor_cells(cell).map(|(_, neighbor)| neighbor))
+        });
+
+        cells.filter_map(|cell| {
+            self.endpoint_owner(cell)
+                .map(|owner| (self.shape.uncell(cell), owner))
+        })
+    }
+
+    /// A bit for each entry of [Direction::ALL] that `index` has an empty neighbor in.
+    pub fn open_directions_vc(&self, index: VirtD3) -> u16 {
+        let Some(cell) = self.shape.sphere_cell(index) else {
+            return 0;
+        };
+
+        let mut open = 0;
+        for (d, neighbor) in self.neighbor_cells(cell) {
+            if self.has(neighbor, EMPTY) {
+                open |= 1 << d;
+            }
+        }
+
+        open
+    }
+
+    /// Every endpoint `player` owns that has an empty space next to it in `direction`, in
+    /// [Grid::enumerate_vc] order. Works on 64 spaces at a time, so it's much faster than
+    /// checking each endpoint's neighbors.
+    pub fn endpoints_open_towards(
+        &self,
+        player: SpicePlayer,
+        direction: Direction,
+    ) -> impl Iterator<Item = VirtD3> + '_ {
+        let delta = self.shape.delta(direction);
+        let has_neighbor = &self.shape.has_neighbor[direction as usize];
+
+        has_neighbor.iter().enumerate().flat_map(move |(w, &mask)| {
+            let endpoints = self.words[w * PLANES + ENDPOINTS + player as usize] & mask;
+
+            // the empty plane, shifted so that each endpoint lines up with its neighbor
+            let mut open = if endpoints == 0 {
+                0
+            } else {
+                endpoints & self.plane_bits_from(EMPTY, (w * 64) as isize + delta)
+            };
+
+            iter::from_fn(move || {
+                (open != 0).then(|| {
+                    let bit = open.trailing_zeros() as usize;
+                    open &= open - 1;
+
+                    self.shape.uncell(w * 64 + bit)
+                })
+            })
+        })
+    }
+
+    /// Where `index` is kept, if it's in the grid.
+    pub fn spot(&self, index: VirtD3) -> Option<Spot> {
+        self.shape.sphere_cell(index).map(|cell| Spot {
+            coord: index,
+            cell: cell as u32,
+        })
+    }
+
+    /// # Panics
+    ///
+    /// May panic if `spot` came from a grid with a different radius.
+    pub fn get_spot(&self, spot: Spot) -> &GridSpace {
+        self.read_cell(spot.cell as usize)
+    }
 
-        match self.packed_spaces[CENTER] {
-            Some(GridSpace::Endpoint { owner, .. }) => Some(owner),
+    /// # Panics
+    ///
+    /// May panic if `spot` came from a grid with a different radius.
+    pub fn set_spot(&mut self, spot: Spot, value: GridSpace) {
+        self.write_cell(spot.cell as usize, &value);
+    }
+
+    /// The space next to `index` in `direction`, if both are in the grid.
+    pub fn neighbor(&self, index: VirtD3, direction: Direction) -> Option<VirtD3> {
+        self.ray(index, direction).next().map(|s| s.coord)
+    }
+
+    /// Every space from `index` to the edge of the grid in `direction`, in order, looked up
+    /// in a table instead of worked out one step at a time. Empty if `index` isn't in the
+    /// grid.
+    pub fn ray(&self, index: VirtD3, direction: Direction) -> Ray {
+        match self.spot(index) {
+            Some(spot) => self.ray_from(spot, direction),
+            None => Ray {
+                shape: self.shape.clone(),
+                // the end of the last ray
+                next: self.shape.rays.len() - 1,
+            },
+        }
+    }
+
+    /// Like [Grid::ray], without having to find `spot` first.
+    pub fn ray_from(&self, spot: Spot, direction: Direction) -> Ray {
+        Ray {
+            shape: self.shape.clone(),
+            next: self.shape.ray_starts[spot.cell as usize][direction as usize] as usize,
+        }
+    }
+
+    pub fn center_owner(&self) -> Option<SpicePlayer> {
+        match self.get_vc(virt_d3(0, 0, 0)) {
+            Some(GridSpace::Endpoint { owner, .. }) => Some(*owner),
             Some(GridSpace::Empty) | Some(GridSpace::LineSegment { .. }) | None => None,
             Some(GridSpace::Blocked) => {
                 panic!("the game should end before the center can become blocked")
@@ -146,37 +487,355 @@ impl Grid {
         }
     }
 
+    /// The cells next to `cell`, which must be in the sphere, along with the index in
+    /// [Direction::ALL] of the direction each is in.
     #[inline]
-    fn axis_length() -> usize {
-        // each axis is two spans of GRID_CONSTANT_I length, plus 1 space for the origin
-        (GRID_CONSTANT_I * 2 + 1) as usize
+    fn neighbor_cells(&self, cell: usize) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let rays = &self.shape.rays;
+
+        // each ray starts with the neighbor that way, if there is one
+        self.shape.ray_starts[cell]
+            .iter()
+            .enumerate()
+            .filter_map(move |(d, &start)| {
+                let next = rays[start as usize];
+                (next != RAY_END).then_some((d, next as usize))
+            })
     }
 
     #[inline]
-    fn indexify(virt: VirtD3) -> (usize, usize, usize) {
+    fn endpoint_owner(&self, cell: usize) -> Option<SpicePlayer> {
+        [SpicePlayer::Red, SpicePlayer::Blue]
+            .into_iter()
+            .find(|&p| self.has(cell, ENDPOINTS + p as usize))
+    }
+
+    #[inline]
+    fn has(&self, cell: usize, plane: usize) -> bool {
+        self.words[cell / 64 * PLANES + plane] & (1 << (cell % 64)) != 0
+    }
+
+    /// The 64 bits of `plane` for the cells from `start` on. Cells off either end of the
+    /// cube are never in a plane.
+    #[inline]
+    fn plane_bits_from(&self, plane: usize, start: isize) -> u64 {
+        let word = |w: isize| {
+            usize::try_from(w)
+                .ok()
+                .and_then(|w| self.words.get(w * PLANES + plane))
+                .copied()
+                .unwrap_or(0)
+        };
+
+        let (w, shift) = (start.div_euclid(64), start.rem_euclid(64) as u32);
+        if shift == 0 {
+            word(w)
+        } else {
+            (word(w) >> shift) | (word(w + 1) << (64 - shift))
+        }
+    }
+
+    fn read_cell(&self, cell: usize) -> &'static GridSpace {
+        if self.has(cell, EMPTY) {
+            &GridSpace::Empty
+        } else if self.has(cell, BLOCKED) {
+            &GridSpace::Blocked
+        } else if let Some(owner) = self.endpoint_owner(cell) {
+            let connected_lines = (0..CONNECTION_BITS)
+                .filter(|&b| self.has(cell, CONNECTIONS + b))
+                .map(|b| 1 << b)
+                .sum::<usize>();
+
+            &ENDPOINT_SPACES[owner as usize][connected_lines]
+        } else {
+            let axis = Axis::ALL
+                .into_iter()
+                .find(|&a| self.has(cell, LINES + a as usize))
+                .expect("every cell in the sphere should be in exactly one plane");
+
+            &LINE_SPACES[axis as usize][self.has(cell, HARDENED) as usize]
+        }
+    }
+
+    fn write_cell(&mut self, cell: usize, value: &GridSpace) {
+        let base = cell / 64 * PLANES;
+        let bit = 1 << (cell % 64);
+        let planes = |words: &[u64]| {
+            (0..PLANES)
+                .filter(|&p| words[base + p] & bit != 0)
+                .fold(0u32, |planes, p| planes | 1 << p)
+        };
+        let old_planes = planes(&self.words);
+
+        for word in &mut self.words[base..base + PLANES] {
+            *word &= !bit;
+        }
+
+        let mut add = |plane: usize| self.words[base + plane] |= bit;
+
+        match *value {
+            GridSpace::Empty => add(EMPTY),
+            GridSpace::Blocked => add(BLOCKED),
+            GridSpace::LineSegment { axis, hardened } => {
+                add(LINES + axis as usize);
+
+                if hardened {
+                    add(HARDENED);
+                }
+            }
+            GridSpace::Endpoint {
+                owner,
+                connected_lines,
+            } => {
+                add(ENDPOINTS + owner as usize);
+
+                for b in 0..CONNECTION_BITS {
+                    if connected_lines & (1 << b) != 0 {
+                        add(CONNECTIONS + b);
+                    }
+                }
+            }
+        }
+
+        // the empty plane follows from the others, so it's left out
+        let mut flipped = (old_planes ^ planes(&self.words)) & !(1 << EMPTY);
+        while flipped != 0 {
+            let plane = flipped.trailing_zeros() as usize;
+            flipped &= flipped - 1;
+
+            self.zobrist ^= key(Feature::Space, (cell * PLANES + plane) as u64);
+        }
+    }
+}
+
+impl GridSpace {
+    /// The same space, with each player's endpoints given to the other.
+    pub fn with_colors_swapped(&self) -> GridSpace {
+        match *self {
+            GridSpace::Endpoint {
+                owner,
+                connected_lines,
+            } => GridSpace::Endpoint {
+                owner: owner.opponent(),
+                connected_lines,
+            },
+            ref space => space.clone(),
+        }
+    }
+}
+
+impl Transform for GridSpace {
+    fn transformed(&self, symmetry: Symmetry) -> Self {
+        match *self {
+            GridSpace::LineSegment { axis, hardened } => GridSpace::LineSegment {
+                axis: axis.transformed(symmetry),
+                hardened,
+            },
+            ref space => space.clone(),
+        }
+    }
+}
+
+impl Grid {
+    /// The same grid, with each player's endpoints given to the other.
+    pub fn with_colors_swapped(&self) -> Grid {
+        let mut rval = Grid::with_radius(self.radius());
+        for (c, s) in self.enumerate_vc() {
+            rval.set_vc_unchecked(c, s.with_colors_swapped());
+        }
+
+        rval
+    }
+}
+
+impl Transform for Grid {
+    /// # Panics
+    ///
+    /// Panics if `symmetry` doesn't [preserve grids](Symmetry::preserves_grids).
+    fn transformed(&self, symmetry: Symmetry) -> Self {
+        assert!(
+            symmetry.preserves_grids(),
+            "{symmetry:?} doesn't map grids onto themselves"
+        );
+
+        let mut rval = Grid::with_radius(self.radius());
+        for (c, s) in self.enumerate_vc() {
+            rval.set_vc_unchecked(c.transformed(symmetry), s.transformed(symmetry));
+        }
+
+        rval
+    }
+}
+
+impl Iterator for Ray {
+    type Item = Spot;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.shape.rays[self.next] {
+            RAY_END => None,
+            cell => {
+                self.next += 1;
+                Some(Spot {
+                    coord: self.shape.uncell(cell as usize),
+                    cell,
+                })
+            }
+        }
+    }
+}
+
+impl Shape {
+    fn cached(radius: f32) -> Arc<Self> {
+        let mut shapes = SHAPES
+            .lock()
+            .expect("shape cache lock shouldn't be poisoned");
+
+        let shape = match shapes.iter().position(|s| s.radius == radius.to_bits()) {
+            Some(i) => shapes.remove(i),
+            None => Arc::new(Self::new(radius)),
+        };
+
+        shapes.insert(0, shape.clone());
+        shapes.truncate(CACHED_SHAPES);
+
+        shape
+    }
+
+    fn new(radius: f32) -> Self {
+        // the largest integer strictly less than the radius
+        let extent = radius.ceil() as i8 - 1;
+        let width = (extent * 2 + 1) as usize;
+        let cell_count = width * width * width;
+        let word_count = cell_count.div_ceil(64);
+
+        let mut shape = Self {
+            extent,
+            width,
+            radius: radius.to_bits(),
+            in_sphere: vec![0; word_count],
+            coords: Vec::with_capacity(cell_count),
+            has_neighbor: Default::default(),
+            rays: Vec::new(),
+            ray_starts: vec![[RAY_END; 12]; cell_count],
+        };
+
+        let mut spaces = Vec::new();
+        for i in -extent..=extent {
+            for j in -extent..=extent {
+                for k in -extent..=extent {
+                    let virt = virt_d3(i, j, k);
+                    shape.coords.push(virt);
+
+                    if virt.length_squared() < radius * radius {
+                        let cell = shape.cell(shape.indexify(virt));
+                        shape.in_sphere[cell / 64] |= 1 << (cell % 64);
+                        spaces.push((virt, cell));
+                    }
+                }
+            }
+        }
+
+        for d in Direction::ALL {
+            let mut mask = vec![0; word_count];
+
+            // the sphere is convex, so each line across it is unbroken, and starts wherever
+            // the space behind is outside it
+            for &(start, _) in &spaces {
+                if shape.sphere_cell(start - d).is_some() {
+                    continue;
+                }
+
+                let mut previous: Option<usize> = None;
+                let mut virt = start;
+                while let Some(cell) = shape.sphere_cell(virt) {
+                    if let Some(previous) = previous {
+                        shape.ray_starts[previous][d as usize] = shape.rays.len() as u32;
+                        mask[previous / 64] |= 1 << (previous % 64);
+                    }
+
+                    shape.rays.push(cell as u32);
+                    previous = Some(cell);
+                    virt += d;
+                }
+
+                // the last space's ray is empty
+                if let Some(previous) = previous {
+                    shape.ray_starts[previous][d as usize] = shape.rays.len() as u32;
+                }
+                shape.rays.push(RAY_END);
+            }
+
+            shape.has_neighbor[d as usize] = mask;
+        }
+
+        shape
+    }
+
+    #[inline]
+    fn indexify(&self, virt: VirtD3) -> (usize, usize, usize) {
         let VirtD3 { i, j, k } = virt;
 
         (
-            (i + GRID_CONSTANT_I) as usize,
-            (j + GRID_CONSTANT_I) as usize,
-            (k + GRID_CONSTANT_I) as usize,
+            (i + self.extent) as usize,
+            (j + self.extent) as usize,
+            (k + self.extent) as usize,
         )
     }
 
-    #[inline]
-    fn unindexify(idx: (usize, usize, usize)) -> VirtD3 {
+    // only used to check indexify
+    #[cfg(test)]
+    fn unindexify(&self, idx: (usize, usize, usize)) -> VirtD3 {
         let (t, u, v) = idx;
 
         virt_d3(
-            t as i8 - GRID_CONSTANT_I,
-            u as i8 - GRID_CONSTANT_I,
-            v as i8 - GRID_CONSTANT_I,
+            t as i8 - self.extent,
+            u as i8 - self.extent,
+            v as i8 - self.extent,
         )
     }
 
     #[inline]
-    fn in_sphere(virt: VirtD3) -> bool {
-        virt.length_squared() < GRID_CONSTANT_F * GRID_CONSTANT_F
+    fn cell(&self, idx: (usize, usize, usize)) -> usize {
+        let (t, u, v) = idx;
+
+        (t * self.width + u) * self.width + v
+    }
+
+    /// How far apart the cells of neighbors in `direction` are.
+    fn delta(&self, direction: Direction) -> isize {
+        let VirtD3 { i, j, k } = direction.into();
+        let width = self.width as isize;
+
+        (i as isize * width + j as isize) * width + k as isize
+    }
+
+    #[inline]
+    fn uncell(&self, cell: usize) -> VirtD3 {
+        self.coords[cell]
+    }
+
+    /// The cell `virt` would be in, if it's in the cube around the sphere.
+    #[inline]
+    fn cube_cell(&self, virt: VirtD3) -> Option<usize> {
+        let VirtD3 { i, j, k } = virt;
+        let extent = self.extent as u8;
+
+        // checked first, so that indexify can't overflow
+        let in_bounds =
+            i.unsigned_abs() <= extent && j.unsigned_abs() <= extent && k.unsigned_abs() <= extent;
+
+        in_bounds.then(|| self.cell(self.indexify(virt)))
+    }
+
+    #[inline]
+    fn sphere_cell(&self, virt: VirtD3) -> Option<usize> {
+        self.cube_cell(virt).filter(|&cell| self.contains(cell))
+    }
+
+    #[inline]
+    fn contains(&self, cell: usize) -> bool {
+        self.in_sphere[cell / 64] & (1 << (cell % 64)) != 0
     }
 }
 
@@ -346,15 +1005,168 @@ mod tests {
     }
 
     #[rstest]
-    fn indexify_unindexify_equivalence() {
+    fn every_space_survives_packing(mut empty_grid: Grid) {
+        let endpoints = [SpicePlayer::Red, SpicePlayer::Blue]
+            .into_iter()
+            .flat_map(|owner| {
+                [0, 1, 12, u8::MAX].map(|connected_lines| GridSpace::Endpoint {
+                    owner,
+                    connected_lines,
+                })
+            });
+        let lines = Axis::ALL.into_iter().flat_map(|axis| {
+            [false, true].map(|hardened| GridSpace::LineSegment { axis, hardened })
+        });
+        let spaces = [GridSpace::Empty, GridSpace::Blocked]
+            .into_iter()
+            .chain(endpoints)
+            .chain(lines);
+
+        for space in spaces {
+            // overwriting a different space each time, to check that nothing is left over
+            empty_grid.set_vc_unchecked(virt_d3(1, 2, 3), space.clone());
+            assert_eq!(empty_grid.get_vc(virt_d3(1, 2, 3)), Some(&space));
+        }
+    }
+
+    #[rstest]
+    fn open_endpoints_match_their_neighbors(mut empty_grid: Grid) {
+        let mut rng = StdRng::seed_from_u64(0);
+        let coords: Vec<VirtD3> = empty_grid.enumerate_vc().map(|(c, _)| c).collect();
+
+        for &c in &coords {
+            let space = match rng.gen_range(0..4) {
+                0 => GridSpace::Empty,
+                1 => GridSpace::Blocked,
+                _ => GridSpace::Endpoint {
+                    owner: SpicePlayer::Blue,
+                    connected_lines: 1,
+                },
+            };
+            empty_grid.set_vc_unchecked(c, space);
+        }
+
+        for d in Direction::ALL {
+            let expected: Vec<VirtD3> = coords
+                .iter()
+                .copied()
+                .filter(|&c| {
+                    matches!(empty_grid.get_vc(c), Some(GridSpace::Endpoint { .. }))
+                        && empty_grid.is_valid_and_empty_vc(c + d)
+                })
+                .collect();
+
+            let actual: Vec<VirtD3> = empty_grid
+                .endpoints_open_towards(SpicePlayer::Blue, d)
+                .collect();
+
+            assert_eq!(actual, expected, "{d:?}");
+            assert_eq!(
+                empty_grid
+                    .endpoints_open_towards(SpicePlayer::Red, d)
+                    .count(),
+                0
+            );
+        }
+    }
+
+    #[rstest]
+    #[case(1., true)]
+    #[case(MAX_RADIUS, true)]
+    #[case(0.5, false)]
+    #[case(MAX_RADIUS + 1., false)]
+    #[case(f32::NAN, false)]
+    fn radii_are_checked(#[case] radius: f32, #[case] valid: bool) {
+        assert_eq!(Grid::try_with_radius(radius).is_some(), valid);
+    }
+
+    #[rstest]
+    #[case(GRID_CONSTANT_F)]
+    #[case(1.5)]
+    #[case(7.)]
+    fn rays_match_stepping(#[case] radius: f32) {
+        let grid = Grid::with_radius(radius);
+
+        for (start, _) in grid.enumerate_vc() {
+            for d in Direction::ALL {
+                let mut expected = Vec::new();
+                let mut coord = start + d;
+                while grid.get_vc(coord).is_some() {
+                    expected.push(coord);
+                    coord += d;
+                }
+
+                let actual: Vec<VirtD3> = grid.ray(start, d).map(|s| s.coord).collect();
+                assert_eq!(actual, expected, "{start} {d:?}");
+                assert_eq!(grid.neighbor(start, d), expected.first().copied());
+            }
+        }
+    }
+
+    #[rstest]
+    fn rays_from_outside_are_empty(empty_grid: Grid) {
+        assert_eq!(
+            empty_grid
+                .ray(virt_d3(5, 5, 5), Direction::DownSouth)
+                .count(),
+            0
+        );
+        assert_eq!(
+            empty_grid.neighbor(virt_d3(i8::MIN, 0, 0), Direction::UpNorth),
+            None
+        );
+    }
+
+    #[rstest]
+    fn spots_find_their_space(mut empty_grid: Grid) {
+        let spot = empty_grid.spot(virt_d3(1, 2, 3)).unwrap();
+        empty_grid.set_spot(spot, GridSpace::Blocked);
+
+        assert_eq!(spot.coord, virt_d3(1, 2, 3));
+        assert_eq!(empty_grid.get_spot(spot), &GridSpace::Blocked);
+        assert_eq!(
+            empty_grid.get_vc(virt_d3(1, 2, 3)),
+            Some(&GridSpace::Blocked)
+        );
+        assert_eq!(empty_grid.spot(virt_d3(5, 5, 5)), None);
+    }
+
+    #[rstest]
+    fn grids_share_shapes() {
+        assert!(Arc::ptr_eq(
+            &Grid::with_radius(3.5).shape,
+            &Grid::with_radius(3.5).shape
+        ));
+    }
+
+    #[rstest]
+    fn shape_cache_is_bounded() {
+        for i in 0..CACHED_SHAPES * 2 {
+            Grid::with_radius(2. + i as f32 / 8.);
+        }
+
+        assert!(SHAPES.lock().unwrap().len() <= CACHED_SHAPES);
+    }
+
+    #[rstest]
+    fn clones_are_independent(empty_grid: Grid) {
+        let mut clone = empty_grid.clone();
+        clone.set_vc_unchecked(virt_d3(0, 0, 0), GridSpace::Blocked);
+
+        assert_ne!(clone, empty_grid);
+        assert_eq!(empty_grid.get_vc(virt_d3(0, 0, 0)), Some(&GridSpace::Empty));
+    }
+
+    #[rstest]
+    fn indexify_unindexify_equivalence(empty_grid: Grid) {
         for _ in 0..100 {
             let virt = virt_d3(
                 thread_rng().gen_range(i8::MIN..=i8::MAX - GRID_CONSTANT_I),
                 thread_rng().gen_range(i8::MIN..=i8::MAX - GRID_CONSTANT_I),
                 thread_rng().gen_range(i8::MIN..=i8::MAX - GRID_CONSTANT_I),
             );
-            let index = Grid::indexify(virt);
-            let unindex = Grid::unindexify(index);
+            let index = empty_grid.shape.indexify(virt);
+            let unindex = empty_grid.shape.unindexify(index);
 
             assert_eq!(
                 virt, unindex,
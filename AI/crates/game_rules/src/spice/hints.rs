@@ -0,0 +1,317 @@
+//! Move suggestions for human players: a short search ranks the candidates, and each
+//! suggestion is annotated with the reasons it might be a good idea.
+
+use std::cmp::Reverse;
+
+use mcts::{GameState, SearchParameters, Searcher};
+use serde::{Deserialize, Serialize};
+
+use super::{coord::*, grid::*, moves::*, players::*, SpiceState};
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Hint {
+    pub move_: SpiceMove,
+    /// The search's estimated chance of winning after this move, between 0 and 1, with draws
+    /// counting as half a win.
+    pub win_rate: f32,
+    /// How much attention the search gave this move. Higher is more confident.
+    pub visits: i32,
+    pub reasons: Vec<HintReason>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum HintReason {
+    /// Ends the line on the center, winning the game.
+    CapturesCenter,
+    /// Cuts through a line attached to an opponent endpoint.
+    CutsOpponentLine,
+    /// Leaves an opponent endpoint with no lines, blocking it.
+    BlocksOpponentEndpoint,
+    /// Leaves the opponent without a move that captures the center, when they had one.
+    StopsOpponentCenterThreat,
+    /// Sets up a move that captures the center next turn.
+    ThreatensCenter,
+}
+
+/// Searches `state` and returns up to `count` of the current player's moves, most
+/// promising first.
+pub fn suggest_moves(state: &SpiceState, parameters: &SearchParameters, count: usize) -> Vec<Hint> {
+    if state.is_over() {
+        return Vec::new();
+    }
+
+    // a proven move comes back without statistics, and we want to rank several moves
+    let mut searcher = Searcher::new(SearchParameters {
+        solver_node_budget: None,
+        ..parameters.clone()
+    });
+    searcher.search(state.clone());
+
+    let mut children = searcher
+        .root_statistics()
+        .expect("searching without the solver should always build a tree")
+        .children;
+    children.sort_by_key(|c| Reverse(c.visits));
+
+    children
+        .into_iter()
+        .take(count)
+        .map(|c| Hint {
+            reasons: move_reasons(state, &c.move_),
+            // scores are for the player making the move, from -1 for a loss to 1 for a win
+            win_rate: (c.score / c.visits.max(1) as f32 + 1.) / 2.,
+            visits: c.visits,
+            move_: c.move_,
+        })
+        .collect()
+}
+
+/// Everything notable that `move_` would do for the player whose turn it is in `state`, if
+/// it's legal.
+pub fn explain_move(
+    state: &SpiceState,
+    move_: &SpiceMove,
+) -> Result<Vec<HintReason>, SpiceRuleError> {
+    state.check_move(move_)?;
+
+    Ok(move_reasons(state, move_))
+}
+
+fn move_reasons(state: &SpiceState, move_: &SpiceMove) -> Vec<HintReason> {
+    let player = state.player;
+    let opponent = player.opponent();
+    let after = state.apply_move(move_);
+
+    let mut reasons = Vec::new();
+
+    if after.grid.center_owner() == Some(player) {
+        // nothing else matters once the game is won
+        return vec![HintReason::CapturesCenter];
+    }
+
+    let before_endpoints = endpoint_connections(state, opponent);
+    let after_endpoints = endpoint_connections(&after, opponent);
+
+    let blocked = before_endpoints
+        .iter()
+        .any(|(c, _)| !after_endpoints.iter().any(|(a, _)| a == c));
+    let cut = blocked
+        || before_endpoints.iter().any(|(c, lines)| {
+            after_endpoints
+                .iter()
+                .any(|(a, after_lines)| a == c && after_lines < lines)
+        });
+
+    if cut {
+        reasons.push(HintReason::CutsOpponentLine);
+    }
+
+    if blocked {
+        reasons.push(HintReason::BlocksOpponentEndpoint);
+    }
+
+    if can_capture_center(state, opponent) && !can_capture_center(&after, opponent) {
+        reasons.push(HintReason::StopsOpponentCenterThreat);
+    }
+
+    if can_capture_center(&after, player) {
+        reasons.push(HintReason::ThreatensCenter);
+    }
+
+    reasons
+}
+
+fn endpoint_connections(state: &SpiceState, player: SpicePlayer) -> Vec<(VirtD3, u8)> {
+    state
+        .move_cache
+        .endpoint_coords(player)
+        .iter()
+        .filter_map(|&c| match state.grid.get_vc(c) {
+            Some(GridSpace::Endpoint {
+                connected_lines, ..
+            }) => Some((c, *connected_lines)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Whether `player` would have a center-capturing move if it were their turn.
+pub(super) fn can_capture_center(state: &SpiceState, player: SpicePlayer) -> bool {
+    if state.grid.center_owner().is_some() {
+        return false;
+    }
+
+    let hypothetical = SpiceState {
+        player,
+        ..state.clone()
+    };
+
+    hypothetical
+        .available_moves()
+        .any(|m| hypothetical.apply_move(&m).grid.center_owner() == Some(player))
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::*;
+
+    use super::*;
+    use crate::spice::{config::*, direction::*, fixtures::*};
+
+    fn endpoint(owner: SpicePlayer, connected_lines: u8) -> GridSpace {
+        GridSpace::Endpoint {
+            owner,
+            connected_lines,
+        }
+    }
+
+    fn smove(source: VirtD3, direction: Direction) -> SpiceMove {
+        SpiceMove { source, direction }
+    }
+
+    #[fixture]
+    fn parameters() -> SearchParameters {
+        SearchParameters {
+            exploration_factor: std::f32::consts::FRAC_1_SQRT_2,
+            search_iterations: 50,
+            solver_node_budget: None,
+            rollout_cutoff: None,
+        }
+    }
+
+    #[rstest]
+    fn explains_center_capture() {
+        let state = blocked_state(&[
+            (virt_d3(2, 0, 0), endpoint(SpicePlayer::Blue, 0)),
+            (virt_d3(1, 0, 0), GridSpace::Empty),
+            (virt_d3(0, 0, 0), GridSpace::Empty),
+        ]);
+
+        assert_eq!(
+            explain_move(&state, &smove(virt_d3(2, 0, 0), Direction::DownSouth)).unwrap(),
+            vec![HintReason::CapturesCenter]
+        );
+    }
+
+    #[rstest]
+    fn explains_center_threat() {
+        let state = blocked_state(&[
+            (virt_d3(1, -1, 0), endpoint(SpicePlayer::Blue, 0)),
+            (virt_d3(1, 0, 0), GridSpace::Empty),
+            (virt_d3(0, 0, 0), GridSpace::Empty),
+        ]);
+
+        assert_eq!(
+            explain_move(&state, &smove(virt_d3(1, -1, 0), Direction::UpEast)).unwrap(),
+            vec![HintReason::ThreatensCenter]
+        );
+    }
+
+    #[rstest]
+    fn explains_cuts_and_blocks() {
+        let mut state = SpiceState::initial_state();
+        state.grid.set_vc_unchecked(
+            virt_d3(0, 3, 3),
+            GridSpace::LineSegment {
+                axis: Axis::NeSw,
+                hardened: false,
+            },
+        );
+        state
+            .grid
+            .set_vc_unchecked(virt_d3(0, 3, 4), endpoint(SpicePlayer::Red, 1));
+        state
+            .grid
+            .set_vc_unchecked(virt_d3(0, 3, 2), endpoint(SpicePlayer::Red, 2));
+        state.move_cache = MoveCache::from_grid(&state.grid);
+
+        assert_eq!(
+            explain_move(&state, &smove(virt_d3(3, 3, 3), Direction::DownSouth)).unwrap(),
+            vec![
+                HintReason::CutsOpponentLine,
+                HintReason::BlocksOpponentEndpoint
+            ]
+        );
+    }
+
+    #[rstest]
+    fn explains_stopping_threats() {
+        let state = blocked_state(&[
+            (virt_d3(1, -1, 0), endpoint(SpicePlayer::Blue, 0)),
+            (virt_d3(0, -1, 0), GridSpace::Empty),
+            (virt_d3(0, 0, 0), GridSpace::Empty),
+            (virt_d3(0, -2, 0), endpoint(SpicePlayer::Red, 0)),
+        ]);
+
+        // blue's new endpoint sits in red's path to the center, and has a path of its own
+        assert_eq!(
+            explain_move(&state, &smove(virt_d3(1, -1, 0), Direction::DownSouth)).unwrap(),
+            vec![
+                HintReason::StopsOpponentCenterThreat,
+                HintReason::ThreatensCenter
+            ]
+        );
+    }
+
+    #[rstest]
+    fn suggestions_are_ranked_and_bounded(parameters: SearchParameters, small_state: SpiceState) {
+        let hints = suggest_moves(&small_state, &parameters, 3);
+
+        assert!(!hints.is_empty() && hints.len() <= 3);
+        for pair in hints.windows(2) {
+            assert!(pair[0].visits >= pair[1].visits);
+        }
+        for hint in &hints {
+            assert!((0. ..=1.).contains(&hint.win_rate));
+            assert!(small_state.available_moves().any(|m| m == hint.move_));
+        }
+    }
+
+    #[rstest]
+    fn suggestions_include_center_capture(parameters: SearchParameters) {
+        let state = blocked_state(&[
+            (virt_d3(2, 0, 0), endpoint(SpicePlayer::Blue, 0)),
+            (virt_d3(1, 0, 0), GridSpace::Empty),
+            (virt_d3(0, 0, 0), GridSpace::Empty),
+            (virt_d3(2, 1, 0), GridSpace::Empty),
+        ]);
+
+        let hints = suggest_moves(&state, &parameters, usize::MAX);
+
+        assert_eq!(hints[0].reasons, vec![HintReason::CapturesCenter]);
+        assert_eq!(hints[0].win_rate, 1.);
+    }
+
+    #[rstest]
+    fn win_rates_account_for_the_reply(
+        parameters: SearchParameters,
+        blockable_center_state: SpiceState,
+    ) {
+        let state = blockable_center_state;
+        let block = smove(virt_d3(1, -2, 0), Direction::SouthEast);
+
+        let hints = suggest_moves(&state, &parameters, usize::MAX);
+
+        assert_eq!(hints.len(), 2);
+        assert_eq!(hints[0].move_, block);
+        assert_eq!(hints[0].win_rate, 0.5);
+        assert_eq!(hints[1].win_rate, 0.);
+    }
+
+    #[rstest]
+    fn illegal_moves_are_not_explained() {
+        let state = SpiceState::initial_state();
+
+        assert_eq!(
+            explain_move(&state, &smove(virt_d3(0, 0, 0), Direction::UpNorth)),
+            Err(SpiceRuleError::NotAnEndpoint(virt_d3(0, 0, 0)))
+        );
+    }
+
+    #[rstest]
+    fn no_suggestions_when_game_is_over(parameters: SearchParameters) {
+        let state = blocked_state(&[(virt_d3(0, 0, 0), endpoint(SpicePlayer::Red, 0))]);
+
+        assert_eq!(suggest_moves(&state, &parameters, 3), vec![]);
+    }
+}
@@ -1,77 +1,194 @@
 // spice - in a 3D hexagonal grid (face-centered cubic), pieces gain territory by moving
 // in the longest straight line available to them
 
+mod analysis;
+mod config;
 mod coord;
+mod diff;
 mod direction;
+mod evaluation;
+#[cfg(test)]
+mod fixtures;
+mod geometry;
 mod grid;
+mod hints;
 mod moves;
+mod notation;
+mod opening_book;
 mod players;
+mod positions;
+#[cfg(test)]
+mod properties;
+mod record;
+mod self_play;
+mod serialization;
+mod symmetry;
+mod td;
+mod validation;
+mod zobrist;
 
 use mcts::GameState;
+use serde::{Deserialize, Serialize};
 
-use self::{coord::*, direction::*, grid::*, moves::*, players::*};
-
-const MAX_MOVES: u16 = 400;
+pub use self::{
+    analysis::*,
+    config::*,
+    coord::{virt_d3, Symmetry, Transform, VirtD3},
+    diff::*,
+    direction::Direction,
+    evaluation::*,
+    grid::{Grid, GridSpace, Ray, Spot},
+    hints::*,
+    moves::{MoveEvent, MovePreview, SpiceMove, SpiceRuleError},
+    notation::{LongMove, MoveParseError, PositionErrorKind, PositionParseError},
+    opening_book::*,
+    players::SpicePlayer,
+    positions::*,
+    record::*,
+    self_play::*,
+    serialization::SERIALIZATION_VERSION,
+    symmetry::*,
+    td::*,
+    validation::*,
+};
+use self::{coord::*, direction::*, grid::*, moves::*, players::*, serialization::*};
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(into = "SpiceStateRepr", try_from = "SpiceStateRepr")]
 pub struct SpiceState {
     grid: Grid,
     player: SpicePlayer,
     move_cache: MoveCache,
     move_count: u16,
+    max_moves: u16,
 }
 
-impl GameState for SpiceState {
-    type Move = SpiceMove;
-
-    type Player = SpicePlayer;
+impl SpiceState {
+    /// The starting position for a game with the layout in `config`. Blue moves first.
+    pub fn from_config(config: &SpiceConfig) -> Result<Self, ConfigError> {
+        let grid = Grid::from_config(config)?;
+        let move_cache = MoveCache::from_grid(&grid);
 
-    type MoveIterator = std::vec::IntoIter<SpiceMove>;
+        Ok(Self {
+            grid,
+            player: SpicePlayer::Blue,
+            move_cache,
+            move_count: 0,
+            max_moves: config.max_moves,
+        })
+    }
 
-    fn initial_state() -> Self {
-        // definitions
+    /// A hash of the grid, the player to move and the move count, for transposition tables
+    /// and repetition checks. Kept up to date as moves are applied, so it's free to ask for.
+    pub fn zobrist_hash(&self) -> u64 {
+        let to_move = match self.player {
+            SpicePlayer::Blue => zobrist::key(zobrist::Feature::BlueToMove, 0),
+            SpicePlayer::Red => 0,
+        };
 
-        let player = SpicePlayer::Blue;
+        self.grid.zobrist_hash()
+            ^ to_move
+            ^ zobrist::key(zobrist::Feature::MoveCount, self.move_count.into())
+    }
 
-        let blue_endpoint_coords = vec![virt_d3(3, 3, 3)];
-        let red_endpoint_coords = vec![virt_d3(-3, -3, -3)];
+    /// Whether the game has ended, with a win, a draw or a player left without moves.
+    pub fn is_over(&self) -> bool {
+        self.terminal_value(self.player).is_some()
+    }
 
-        // generation
+    pub fn check_move(&self, move_: &SpiceMove) -> Result<(), SpiceRuleError> {
+        if self.is_over() {
+            return Err(SpiceRuleError::GameOver);
+        }
 
-        let mut grid: Grid = Default::default();
+        check_move(&self.grid, self.player, move_)
+    }
 
-        for &index in &blue_endpoint_coords {
-            grid.set_vc(
-                index,
-                GridSpace::Endpoint {
-                    owner: SpicePlayer::Blue,
-                    connected_lines: 0,
-                },
-            );
+    /// Applies `move_` for `player`, after checking that it's legal and their turn. Unlike
+    /// [GameState::apply_move], this is safe to use with moves from untrusted sources.
+    pub fn try_apply_move(
+        &self,
+        player: SpicePlayer,
+        move_: &SpiceMove,
+    ) -> Result<Self, SpiceRuleError> {
+        if player != self.player && !self.is_over() {
+            return Err(SpiceRuleError::NotYourTurn(player));
         }
 
-        for &index in &red_endpoint_coords {
-            grid.set_vc(
-                index,
-                GridSpace::Endpoint {
-                    owner: SpicePlayer::Red,
-                    connected_lines: 0,
-                },
-            );
-        }
+        self.check_move(move_)?;
 
-        let move_cache = MoveCache::from_grid(&grid);
+        let state = self.apply_move(move_);
+        self.debug_check_move(move_, &state);
 
-        Self {
+        Ok(state)
+    }
+
+    /// Works out what `move_` would do, if it's legal, without changing anything. Uses the
+    /// same logic as applying the move, so the two always agree.
+    pub fn preview_move(&self, move_: &SpiceMove) -> Result<MovePreview, SpiceRuleError> {
+        self.check_move(move_)?;
+
+        Ok(preview_move(&self.grid, move_, self.player))
+    }
+
+    /// Applies `move_` for the player to move, after checking that it's legal, and lists
+    /// everything that happened in order, for animating the move.
+    pub fn apply_move_with_events(
+        &self,
+        move_: &SpiceMove,
+    ) -> Result<(Self, Vec<MoveEvent>), SpiceRuleError> {
+        self.check_move(move_)?;
+
+        let mut grid = self.grid.clone();
+        let mut move_cache = self.move_cache.clone();
+        let mut events = Vec::new();
+        apply_move_with_events(&mut grid, &mut move_cache, move_, self.player, &mut |e| {
+            events.push(e)
+        });
+
+        let state = Self {
             grid,
-            player,
+            player: self.player.opponent(),
             move_cache,
-            move_count: 0,
+            move_count: self.move_count + 1,
+            max_moves: self.max_moves,
+        };
+        self.debug_check_move(move_, &state);
+
+        Ok((state, events))
+    }
+
+    /// In debug builds, panics if playing `move_` broke an invariant of this position. Ones
+    /// that were already broken are left alone.
+    fn debug_check_move(&self, move_: &SpiceMove, after: &SpiceState) {
+        if cfg!(debug_assertions) && self.validate().is_empty() {
+            let violations = after.validate();
+            assert!(
+                violations.is_empty(),
+                "{move_} broke {self}: {violations:?}"
+            );
         }
     }
+}
+
+impl GameState for SpiceState {
+    type Move = SpiceMove;
+
+    type Player = SpicePlayer;
+
+    type MoveIterator = std::vec::IntoIter<SpiceMove>;
+
+    fn initial_state() -> Self {
+        Self::from_config(&SpiceConfig::default()).expect("the default config should be valid")
+    }
 
     fn available_moves(&self) -> Self::MoveIterator {
-        generate_moves(&self.grid, self.player, &self.move_cache).into_iter()
+        // the move cache still has moves for a won or drawn position, but none of them are legal
+        if self.is_over() {
+            return vec![].into_iter();
+        }
+
+        self.move_cache.moves(self.player).into_iter()
     }
 
     fn next_to_play(&self) -> Self::Player {
@@ -83,24 +200,19 @@ impl GameState for SpiceState {
         let mut move_cache = self.move_cache.clone();
         apply_move(&mut grid, &mut move_cache, move_, self.next_to_play());
 
-        let player = match self.player {
-            SpicePlayer::Red => SpicePlayer::Blue,
-            SpicePlayer::Blue => SpicePlayer::Red,
-        };
-
         Self {
             grid,
-            player,
+            player: self.player.opponent(),
             move_cache,
             move_count: self.move_count + 1,
+            max_moves: self.max_moves,
         }
     }
 
     fn terminal_value(&self, for_player: Self::Player) -> Option<f32> {
         #[inline]
         fn is_draw(state: &SpiceState) -> bool {
-            state.move_count >= MAX_MOVES
-                || out_of_moves(&state.grid, state.player, &state.move_cache)
+            state.move_count >= state.max_moves || state.move_cache.move_count(state.player) == 0
         }
 
         if let Some(owner) = self.grid.center_owner() {
@@ -118,15 +230,309 @@ mod tests {
     use mcts::*;
     use rstest::*;
 
-    use super::*;
+    use super::{fixtures::*, *};
 
     #[rstest]
     fn search_runs_without_panic() {
         let mut searcher = Searcher::new(SearchParameters {
             exploration_factor: std::f32::consts::FRAC_1_SQRT_2,
             search_iterations: 10,
+            solver_node_budget: None,
+            rollout_cutoff: None,
         });
 
         searcher.search(SpiceState::initial_state());
     }
+
+    #[rstest]
+    #[case(SpicePlayer::Blue, "3,3,3:DS", Ok(()))]
+    #[case(
+        SpicePlayer::Red,
+        "-3,-3,-3:UN",
+        Err(SpiceRuleError::NotYourTurn(SpicePlayer::Red))
+    )]
+    #[case(
+        SpicePlayer::Blue,
+        "6,6,6:DS",
+        Err(SpiceRuleError::OutOfBounds(virt_d3(6, 6, 6)))
+    )]
+    #[case(
+        SpicePlayer::Blue,
+        "1,1,1:DS",
+        Err(SpiceRuleError::NotAnEndpoint(virt_d3(1, 1, 1)))
+    )]
+    #[case(SpicePlayer::Blue, "-3,-3,-3:UN", Err(SpiceRuleError::NotYourEndpoint(virt_d3(-3, -3, -3))))]
+    #[case(SpicePlayer::Blue, "3,3,3:UN", Err(SpiceRuleError::DirectionBlocked))]
+    fn try_apply_move_checks_the_rules(
+        #[case] player: SpicePlayer,
+        #[case] move_: &str,
+        #[case] expected: Result<(), SpiceRuleError>,
+    ) {
+        let state = SpiceState::initial_state();
+        let move_: SpiceMove = move_.parse().unwrap();
+
+        let result = state.try_apply_move(player, &move_);
+
+        assert_eq!(
+            result,
+            expected.map(|_| state.apply_move(&move_)),
+            "{move_} for {player:?}"
+        );
+    }
+
+    #[rstest]
+    fn try_apply_move_rejects_moves_after_the_end() {
+        let state: SpiceState = "5.2 0,0,0:R1;3,3,3:B0 b 1 400".parse().unwrap();
+
+        assert_eq!(
+            state.try_apply_move(SpicePlayer::Blue, &"3,3,3:DS".parse().unwrap()),
+            Err(SpiceRuleError::GameOver)
+        );
+    }
+
+    #[rstest]
+    fn spice_conforms() {
+        check_conformance::<SpiceState>(3, 500, 0);
+    }
+
+    #[rstest]
+    #[case("5.2 0,0,0:R1;3,3,3:B0 b 1 400")]
+    #[case("5.2 -3,-3,-3:R0;3,3,3:B0 b 400 400")]
+    fn no_moves_are_available_after_the_end(#[case] notation: &str) {
+        let state: SpiceState = notation.parse().unwrap();
+
+        assert!(state.is_over());
+        assert_eq!(state.available_moves().count(), 0);
+    }
+
+    #[rstest]
+    fn move_events_describe_the_move_in_order() {
+        let state: SpiceState =
+            "5.2 0,3,1:R2;0,3,2:NeSw;0,3,3:NeSw;0,3,4:R1;1,3,1:UnDs;2,3,1:R1;3,3,3:B0 b 0 400"
+                .parse()
+                .unwrap();
+        assert_eq!(state.validate(), vec![]);
+        let move_: SpiceMove = "3,3,3:DS".parse().unwrap();
+
+        let (after, events) = state.apply_move_with_events(&move_).unwrap();
+
+        let line = |i| MoveEvent::LineDrawn {
+            at: virt_d3(i, 3, 3),
+            axis: Axis::UnDs,
+        };
+        assert_eq!(
+            events,
+            vec![
+                MoveEvent::SourceConnected {
+                    at: virt_d3(3, 3, 3),
+                    connected_lines: 1
+                },
+                line(2),
+                line(1),
+                MoveEvent::Hardened {
+                    at: virt_d3(0, 3, 3),
+                    axis: Axis::UnDs,
+                    crossed_axis: Axis::NeSw
+                },
+                MoveEvent::EndpointBlocked {
+                    at: virt_d3(0, 3, 4),
+                    owner: SpicePlayer::Red
+                },
+                MoveEvent::SegmentCut {
+                    at: virt_d3(0, 3, 2),
+                    direction: Direction::SouthWest
+                },
+                MoveEvent::EndpointDisconnected {
+                    at: virt_d3(0, 3, 1),
+                    owner: SpicePlayer::Red,
+                    connected_lines: 1
+                },
+                line(-1),
+                line(-2),
+                line(-3),
+                MoveEvent::EndpointPlaced {
+                    at: virt_d3(-3, 3, 3),
+                    owner: SpicePlayer::Blue
+                },
+            ]
+        );
+        assert_eq!(after, state.apply_move(&move_));
+        assert_eq!(after.validate(), vec![]);
+    }
+
+    #[rstest]
+    fn move_events_need_a_legal_move() {
+        let state = SpiceState::initial_state();
+
+        assert_eq!(
+            state.apply_move_with_events(&"3,3,3:UN".parse().unwrap()),
+            Err(SpiceRuleError::DirectionBlocked)
+        );
+    }
+
+    #[rstest]
+    fn cached_moves_match_a_rescan() {
+        use rand::{rngs::StdRng, seq::IteratorRandom, SeedableRng};
+
+        fn sorted(mut moves: Vec<SpiceMove>) -> Vec<SpiceMove> {
+            moves.sort_by_key(|m| (m.source.i, m.source.j, m.source.k, m.direction as u8));
+            moves
+        }
+
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..20 {
+            let mut state = SpiceState::initial_state();
+
+            while !state.is_over() {
+                assert_eq!(state.move_cache, MoveCache::from_grid(&state.grid));
+
+                for player in [SpicePlayer::Blue, SpicePlayer::Red] {
+                    let cached = state.move_cache.moves(player);
+                    assert_eq!(cached.len(), state.move_cache.move_count(player));
+                    assert_eq!(
+                        sorted(cached),
+                        sorted(generate_moves(&state.grid, player)),
+                        "{state}"
+                    );
+                }
+
+                let move_ = state.available_moves().choose(&mut rng).unwrap();
+                state = state.apply_move(&move_);
+            }
+
+            assert_eq!(
+                out_of_moves(&state.grid, state.player),
+                state.move_cache.move_count(state.player) == 0
+            );
+        }
+    }
+
+    #[rstest]
+    fn transpositions_share_a_hash() {
+        let start: SpiceState = "5.2 -3,-3,-3:R0;3,-3,3:B0;3,3,3:B0 b 0 400"
+            .parse()
+            .unwrap();
+        let play = |moves: [&str; 3]| {
+            moves
+                .into_iter()
+                .fold(start.clone(), |s, m| s.apply_move(&m.parse().unwrap()))
+        };
+
+        let one = play(["3,3,3:DS", "-3,-3,-3:UN", "3,-3,3:DS"]);
+        let other = play(["3,-3,3:DS", "-3,-3,-3:UN", "3,3,3:DS"]);
+        let neither = play(["3,3,3:DS", "-3,-3,-3:UN", "-3,3,3:UN"]);
+
+        assert_eq!(one, other);
+        assert_eq!(one.zobrist_hash(), other.zobrist_hash());
+        assert_ne!(one.zobrist_hash(), neither.zobrist_hash());
+    }
+
+    #[rstest]
+    fn hashes_are_kept_up_to_date() {
+        let mut state = SpiceState::initial_state();
+        let mut seen = std::collections::HashSet::new();
+
+        while !state.is_over() {
+            // parsing builds the grid from scratch
+            let fresh: SpiceState = state.to_string().parse().unwrap();
+            assert_eq!(state.zobrist_hash(), fresh.zobrist_hash(), "{state}");
+            assert!(seen.insert(state.zobrist_hash()));
+
+            state = state.apply_move(&state.available_moves().next().unwrap());
+        }
+
+        // the same grid with the other player to move
+        let mut passed = state.clone();
+        passed.player = state.player.opponent();
+        assert_ne!(state.zobrist_hash(), passed.zobrist_hash());
+    }
+
+    #[rstest]
+    fn preview_matches_applying_the_move() {
+        let state: SpiceState =
+            "5.2 0,3,1:R2;0,3,2:NeSw;0,3,3:NeSw;0,3,4:R1;1,3,1:UnDs;2,3,1:R1;3,3,3:B0 b 0 400"
+                .parse()
+                .unwrap();
+        assert_eq!(state.validate(), vec![]);
+        let move_: SpiceMove = "3,3,3:DS".parse().unwrap();
+
+        let preview = state.preview_move(&move_).unwrap();
+
+        assert_eq!(
+            preview,
+            MovePreview {
+                path: (-3..=2).rev().map(|i| virt_d3(i, 3, 3)).collect(),
+                end: virt_d3(-3, 3, 3),
+                cuts: vec![virt_d3(0, 3, 3)],
+                removed_segments: vec![virt_d3(0, 3, 2)],
+                blocked_endpoints: vec![virt_d3(0, 3, 4)],
+                captures_center: false,
+            }
+        );
+
+        // the state it was previewed on is untouched
+        assert_eq!(
+            state.to_string(),
+            "5.2 0,3,1:R2;0,3,2:NeSw;0,3,3:NeSw;0,3,4:R1;1,3,1:UnDs;2,3,1:R1;3,3,3:B0 b 0 400"
+        );
+
+        let after = state.apply_move(&move_);
+        assert_eq!(
+            after.grid.get_vc(preview.end),
+            Some(&GridSpace::Endpoint {
+                owner: SpicePlayer::Blue,
+                connected_lines: 1
+            })
+        );
+        for coord in &preview.blocked_endpoints {
+            assert_eq!(after.grid.get_vc(*coord), Some(&GridSpace::Blocked));
+        }
+    }
+
+    #[rstest]
+    fn preview_reports_center_capture() {
+        // the line stops on the center because the space past it is blocked
+        let state: SpiceState = "5.2 -3,-3,-3:R0;-1,0,0:X;2,0,0:B0 b 0 400".parse().unwrap();
+
+        let preview = state.preview_move(&"2,0,0:DS".parse().unwrap()).unwrap();
+
+        assert_eq!(preview.end, virt_d3(0, 0, 0));
+        assert!(preview.captures_center);
+    }
+
+    #[rstest]
+    fn preview_needs_a_legal_move() {
+        let state = SpiceState::initial_state();
+
+        assert_eq!(
+            state.preview_move(&"3,3,3:UN".parse().unwrap()),
+            Err(SpiceRuleError::DirectionBlocked)
+        );
+    }
+
+    #[rstest]
+    fn solver_finds_center_capture() {
+        let state = blocked_state(&[
+            (
+                virt_d3(2, 0, 0),
+                GridSpace::Endpoint {
+                    owner: SpicePlayer::Blue,
+                    connected_lines: 0,
+                },
+            ),
+            (virt_d3(1, 0, 0), GridSpace::Empty),
+            (virt_d3(0, 0, 0), GridSpace::Empty),
+        ]);
+
+        let solution = solve(&state, 1_000).unwrap();
+
+        assert_eq!(solution.outcome(), Outcome::Win);
+        assert_eq!(
+            state
+                .apply_move(&solution.best_move.unwrap())
+                .grid
+                .center_owner(),
+            Some(SpicePlayer::Blue)
+        );
+    }
 }
@@ -1,11 +1,12 @@
 use mcts::GameState;
+use serde::{Deserialize, Serialize};
 
-use super::{coord::*, direction::*, grid::*, players::*, SpiceState};
+use super::{coord::*, direction::*, grid::*, players::*, validation::*, SpiceState};
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Serialize, Deserialize)]
 pub struct SpiceMove {
-    source: VirtD3,
-    direction: Direction,
+    pub(super) source: VirtD3,
+    pub(super) direction: Direction,
 }
 
 impl Default for SpiceMove {
@@ -17,10 +18,115 @@ impl Default for SpiceMove {
     }
 }
 
+/// Why a [SpiceMove] can't be played.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum SpiceRuleError {
+    /// The game is already over.
+    GameOver,
+    /// The move was made by the player who isn't next to play.
+    NotYourTurn(SpicePlayer),
+    /// The move's source isn't on the board.
+    OutOfBounds(VirtD3),
+    /// There's no endpoint at the move's source.
+    NotAnEndpoint(VirtD3),
+    /// The endpoint at the move's source belongs to the other player.
+    NotYourEndpoint(VirtD3),
+    /// The space next to the source, in the move's direction, isn't empty.
+    DirectionBlocked,
+}
+
+impl SpiceMove {
+    pub fn new(source: VirtD3, direction: Direction) -> Self {
+        Self { source, direction }
+    }
+
+    pub fn source(&self) -> VirtD3 {
+        self.source
+    }
+
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+}
+
+impl Transform for SpiceMove {
+    fn transformed(&self, symmetry: Symmetry) -> Self {
+        Self {
+            source: self.source.transformed(symmetry),
+            direction: self.direction.transformed(symmetry),
+        }
+    }
+}
+
+/// Something that happened while applying a move, in the order it happened. Enough to
+/// animate a move without re-implementing the rules.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub enum MoveEvent {
+    /// The move's source endpoint gained a connection, the new line.
+    SourceConnected { at: VirtD3, connected_lines: u8 },
+    /// An empty space became part of the new line.
+    LineDrawn { at: VirtD3, axis: Axis },
+    /// The new line crossed an existing line, hardening the space where they cross.
+    Hardened {
+        at: VirtD3,
+        axis: Axis,
+        crossed_axis: Axis,
+    },
+    /// A crossing removed a segment of the line it crossed, `direction` away from the
+    /// crossing.
+    SegmentCut { at: VirtD3, direction: Direction },
+    /// An endpoint at the end of a cut line lost one of its connections.
+    EndpointDisconnected {
+        at: VirtD3,
+        owner: SpicePlayer,
+        connected_lines: u8,
+    },
+    /// An endpoint at the end of a cut line lost its last connection, and was blocked.
+    EndpointBlocked { at: VirtD3, owner: SpicePlayer },
+    /// The new line's endpoint was placed.
+    EndpointPlaced { at: VirtD3, owner: SpicePlayer },
+}
+
+/// What a move would do, worked out without applying it.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct MovePreview {
+    /// Every space the new line would cover, in order from the source, ending at `end`.
+    pub path: Vec<VirtD3>,
+    /// Where the new endpoint would be placed.
+    pub end: VirtD3,
+    /// Every space where the new line would cross and cut an existing line.
+    pub cuts: Vec<VirtD3>,
+    /// Segments of cut lines that would be removed.
+    pub removed_segments: Vec<VirtD3>,
+    /// Endpoints that would lose their last line, and become blocked.
+    pub blocked_endpoints: Vec<VirtD3>,
+    pub captures_center: bool,
+}
+
+/// Each player's endpoints, and the moves open to them, kept up to date as moves are applied
+/// so that listing moves doesn't need to search the grid.
 #[derive(Debug, PartialEq, Eq, Clone, Default)]
 pub struct MoveCache {
-    blue_endpoint_coords: Vec<VirtD3>,
-    red_endpoint_coords: Vec<VirtD3>,
+    blue: PlayerMoves,
+    red: PlayerMoves,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+struct PlayerMoves {
+    /// Kept in [Grid::enumerate_vc] order, so that endpoints can be found by binary search.
+    endpoint_coords: Vec<VirtD3>,
+    /// For each endpoint, a bit per entry of [Direction::ALL] that it could move towards.
+    open_directions: Vec<u16>,
+    move_count: usize,
+}
+
+impl PlayerMoves {
+    /// Where `coord` is in the endpoint list, or where it would go.
+    fn find(&self, coord: VirtD3) -> Result<usize, usize> {
+        // the order cells are laid out in, which is the order the grid enumerates them in
+        self.endpoint_coords
+            .binary_search_by_key(&(coord.i, coord.j, coord.k), |c| (c.i, c.j, c.k))
+    }
 }
 
 impl MoveCache {
@@ -30,6 +136,7 @@ impl MoveCache {
         for (c, s) in grid.enumerate_vc() {
             if let GridSpace::Endpoint { owner, .. } = s {
                 rval.add_endpoint(*owner, c);
+                rval.refresh_endpoint(grid, *owner, c);
             }
         }
 
@@ -37,56 +144,264 @@ impl MoveCache {
     }
 
     fn add_endpoint(&mut self, player: SpicePlayer, coord: VirtD3) {
-        self._endpoint_coords_mut(player).push(coord);
+        let moves = self.player_moves_mut(player);
+        if let Err(i) = moves.find(coord) {
+            moves.endpoint_coords.insert(i, coord);
+            moves.open_directions.insert(i, 0);
+        }
     }
 
     fn remove_endpoint(&mut self, player: SpicePlayer, coord: VirtD3) {
-        self._endpoint_coords_mut(player).retain(|&c| c != coord);
+        let moves = self.player_moves_mut(player);
+        if let Ok(i) = moves.find(coord) {
+            moves.endpoint_coords.remove(i);
+            moves.move_count -= moves.open_directions.remove(i).count_ones() as usize;
+        }
     }
 
-    fn endpoint_coords(&self, player: SpicePlayer) -> &Vec<VirtD3> {
+    /// Works out again which directions the endpoint at `coord` can move towards.
+    fn refresh_endpoint(&mut self, grid: &Grid, player: SpicePlayer, coord: VirtD3) {
+        let moves = self.player_moves_mut(player);
+        let Ok(i) = moves.find(coord) else {
+            return;
+        };
+
+        let open = grid.open_directions_vc(coord);
+
+        moves.move_count -= moves.open_directions[i].count_ones() as usize;
+        moves.move_count += open.count_ones() as usize;
+        moves.open_directions[i] = open;
+    }
+
+    /// Refreshes every endpoint at or next to a space in `changed`, which are the only ones
+    /// whose moves a change could have opened or closed.
+    fn refresh_around(&mut self, grid: &Grid, changed: &[VirtD3]) {
+        for &at in changed {
+            for (coord, owner) in grid.endpoints_around_vc(at) {
+                self.refresh_endpoint(grid, owner, coord);
+            }
+        }
+    }
+
+    /// Everything this cache has wrong about `grid`, compared to building it from scratch.
+    pub(super) fn violations(&self, grid: &Grid) -> Vec<Violation> {
+        let fresh = MoveCache::from_grid(grid);
+        let mut violations = Vec::new();
+
+        for player in [SpicePlayer::Blue, SpicePlayer::Red] {
+            let (cached, actual) = (self.player_moves(player), fresh.player_moves(player));
+            let find = |moves: &PlayerMoves, coord| {
+                // searched in full, in case the cache is out of order too
+                moves.endpoint_coords.iter().position(|&c| c == coord)
+            };
+
+            for (&at, &open) in actual.endpoint_coords.iter().zip(&actual.open_directions) {
+                match find(cached, at) {
+                    None => violations.push(Violation::MissingEndpoint { at, owner: player }),
+                    Some(i) if cached.open_directions[i] != open => {
+                        violations.push(Violation::StaleMoves { at, owner: player })
+                    }
+                    Some(_) => (),
+                }
+            }
+
+            for &at in &cached.endpoint_coords {
+                if find(actual, at).is_none() {
+                    violations.push(Violation::StaleEndpoint { at, owner: player });
+                }
+            }
+
+            if cached.move_count != actual.move_count {
+                violations.push(Violation::WrongMoveCount {
+                    player,
+                    cached: cached.move_count,
+                    actual: actual.move_count,
+                });
+            }
+        }
+
+        violations
+    }
+
+    pub fn endpoint_coords(&self, player: SpicePlayer) -> &Vec<VirtD3> {
+        &self.player_moves(player).endpoint_coords
+    }
+
+    /// Every move open to `player`, the same ones [generate_moves] would find, grouped by
+    /// endpoint.
+    pub fn moves(&self, player: SpicePlayer) -> Vec<SpiceMove> {
+        let moves = self.player_moves(player);
+        let mut rval = Vec::with_capacity(moves.move_count);
+
+        for (&source, &open) in moves.endpoint_coords.iter().zip(&moves.open_directions) {
+            let mut open = open;
+            while open != 0 {
+                let direction = Direction::ALL[open.trailing_zeros() as usize];
+                open &= open - 1;
+
+                rval.push(SpiceMove { source, direction });
+            }
+        }
+
+        rval
+    }
+
+    pub fn move_count(&self, player: SpicePlayer) -> usize {
+        self.player_moves(player).move_count
+    }
+
+    fn player_moves(&self, player: SpicePlayer) -> &PlayerMoves {
         match player {
-            SpicePlayer::Blue => &self.blue_endpoint_coords,
-            SpicePlayer::Red => &self.red_endpoint_coords,
+            SpicePlayer::Blue => &self.blue,
+            SpicePlayer::Red => &self.red,
         }
     }
 
-    fn _endpoint_coords_mut(&mut self, player: SpicePlayer) -> &mut Vec<VirtD3> {
+    fn player_moves_mut(&mut self, player: SpicePlayer) -> &mut PlayerMoves {
         match player {
-            SpicePlayer::Blue => &mut self.blue_endpoint_coords,
-            SpicePlayer::Red => &mut self.red_endpoint_coords,
+            SpicePlayer::Blue => &mut self.blue,
+            SpicePlayer::Red => &mut self.red,
         }
     }
 }
 
-pub fn generate_moves(grid: &Grid, player: SpicePlayer, move_cache: &MoveCache) -> Vec<SpiceMove> {
-    let coords = move_cache.endpoint_coords(player);
-    let mut moves: Vec<SpiceMove> = Vec::with_capacity(coords.len() * 12);
+/// Every move open to `player`, found by searching the whole grid. [MoveCache::moves] finds
+/// the same ones without searching.
+pub fn generate_moves(grid: &Grid, player: SpicePlayer) -> Vec<SpiceMove> {
+    let mut moves = Vec::new();
 
-    for &c in coords {
-        for d in Direction::ALL {
-            if grid.is_valid_and_empty_vc(c + d) {
-                moves.push(SpiceMove {
-                    source: c,
-                    direction: d,
-                });
-            }
-        }
+    for direction in Direction::ALL {
+        // for_each rather than a for loop, since it lets the flat_map inside run as loops
+        grid.endpoints_open_towards(player, direction)
+            .for_each(|source| moves.push(SpiceMove { source, direction }));
     }
 
     moves
 }
 
-pub fn out_of_moves(grid: &Grid, player: SpicePlayer, move_cache: &MoveCache) -> bool {
-    for &c in move_cache.endpoint_coords(player) {
-        for d in Direction::ALL {
-            if grid.is_valid_and_empty_vc(c + d) {
-                return false;
-            }
+/// Checks that `move_` is one of the moves [generate_moves] would produce for `player`.
+pub fn check_move(
+    grid: &Grid,
+    player: SpicePlayer,
+    move_: &SpiceMove,
+) -> Result<(), SpiceRuleError> {
+    match grid.get_vc(move_.source) {
+        None => return Err(SpiceRuleError::OutOfBounds(move_.source)),
+        Some(GridSpace::Endpoint { owner, .. }) if *owner != player => {
+            return Err(SpiceRuleError::NotYourEndpoint(move_.source))
         }
+        Some(GridSpace::Endpoint { .. }) => (),
+        Some(_) => return Err(SpiceRuleError::NotAnEndpoint(move_.source)),
     }
 
-    true
+    if !grid.is_valid_and_empty_vc(move_.source + move_.direction) {
+        return Err(SpiceRuleError::DirectionBlocked);
+    }
+
+    Ok(())
+}
+
+pub fn out_of_moves(grid: &Grid, player: SpicePlayer) -> bool {
+    Direction::ALL
+        .into_iter()
+        .all(|d| grid.endpoints_open_towards(player, d).next().is_none())
+}
+
+/// Read and write access to spaces, so that the same move logic can either change a real
+/// [Grid] or work out what would change without touching one.
+pub trait RayGrid {
+    fn spot(&self, coord: VirtD3) -> Option<Spot>;
+    fn space(&self, at: Spot) -> GridSpace;
+    fn set_space(&mut self, at: Spot, space: GridSpace);
+    /// See [Grid::ray_from].
+    fn ray_from(&self, from: Spot, direction: Direction) -> Ray;
+}
+
+impl RayGrid for Grid {
+    fn spot(&self, coord: VirtD3) -> Option<Spot> {
+        Grid::spot(self, coord)
+    }
+
+    fn space(&self, at: Spot) -> GridSpace {
+        self.get_spot(at).clone()
+    }
+
+    fn set_space(&mut self, at: Spot, space: GridSpace) {
+        self.set_spot(at, space);
+    }
+
+    fn ray_from(&self, from: Spot, direction: Direction) -> Ray {
+        Grid::ray_from(self, from, direction)
+    }
+}
+
+/// A [Grid] seen through whatever changes a move has made to it so far.
+pub struct OverlayGrid<'a> {
+    grid: &'a Grid,
+    changes: Vec<(Spot, GridSpace)>,
+}
+
+impl<'a> OverlayGrid<'a> {
+    pub fn new(grid: &'a Grid) -> Self {
+        Self {
+            grid,
+            changes: Vec::new(),
+        }
+    }
+}
+
+impl RayGrid for OverlayGrid<'_> {
+    fn spot(&self, coord: VirtD3) -> Option<Spot> {
+        self.grid.spot(coord)
+    }
+
+    fn space(&self, at: Spot) -> GridSpace {
+        match self.changes.iter().rev().find(|(s, _)| *s == at) {
+            Some((_, space)) => space.clone(),
+            None => self.grid.space(at),
+        }
+    }
+
+    fn set_space(&mut self, at: Spot, space: GridSpace) {
+        self.changes.push((at, space));
+    }
+
+    fn ray_from(&self, from: Spot, direction: Direction) -> Ray {
+        self.grid.ray_from(from, direction)
+    }
+}
+
+pub fn preview_move(grid: &Grid, move_: &SpiceMove, player: SpicePlayer) -> MovePreview {
+    let mut preview = MovePreview {
+        path: Vec::new(),
+        end: move_.source,
+        cuts: Vec::new(),
+        removed_segments: Vec::new(),
+        blocked_endpoints: Vec::new(),
+        captures_center: false,
+    };
+
+    walk_move(
+        &mut OverlayGrid::new(grid),
+        move_,
+        player,
+        &mut |event| match event {
+            MoveEvent::LineDrawn { at, .. } => preview.path.push(at),
+            MoveEvent::Hardened { at, .. } => {
+                preview.path.push(at);
+                preview.cuts.push(at);
+            }
+            MoveEvent::SegmentCut { at, .. } => preview.removed_segments.push(at),
+            MoveEvent::EndpointBlocked { at, .. } => preview.blocked_endpoints.push(at),
+            MoveEvent::EndpointPlaced { at, .. } => {
+                preview.end = at;
+                preview.captures_center = at == virt_d3(0, 0, 0);
+            }
+            MoveEvent::SourceConnected { .. } | MoveEvent::EndpointDisconnected { .. } => (),
+        },
+    );
+
+    preview
 }
 
 pub fn apply_move(
@@ -95,95 +410,192 @@ pub fn apply_move(
     move_: &SpiceMove,
     player: SpicePlayer,
 ) {
-    // TODO: use anyhow instead of immediately panicking?
+    apply_move_with_events(grid, move_cache, move_, player, &mut |_| ());
+}
+
+/// [apply_move], calling `on_event` with everything that happens along the way.
+pub fn apply_move_with_events(
+    grid: &mut Grid,
+    move_cache: &mut MoveCache,
+    move_: &SpiceMove,
+    player: SpicePlayer,
+    on_event: &mut impl FnMut(MoveEvent),
+) {
+    // spaces that were filled or emptied, which may have opened or closed moves next to them
+    let mut changed = Vec::new();
+
+    walk_move(grid, move_, player, &mut |event| {
+        match event {
+            MoveEvent::LineDrawn { at, .. } | MoveEvent::SegmentCut { at, .. } => changed.push(at),
+            MoveEvent::EndpointBlocked { at, owner } => move_cache.remove_endpoint(owner, at),
+            MoveEvent::EndpointPlaced { at, owner } => {
+                move_cache.add_endpoint(owner, at);
+                changed.push(at);
+            }
+            _ => (),
+        }
+
+        on_event(event);
+    });
+
+    move_cache.refresh_around(grid, &changed);
+}
+
+/// The rules of drawing a line, for any [RayGrid]. Everything that changes is reported to
+/// `on_event`, in order.
+pub fn walk_move(
+    grid: &mut impl RayGrid,
+    move_: &SpiceMove,
+    player: SpicePlayer,
+    on_event: &mut impl FnMut(MoveEvent),
+) {
+    // unchecked, since search only ever applies generated moves. anything from outside
+    // should go through SpiceState::try_apply_move instead
 
-    update_start_endpoint(grid, move_.source);
+    let source = grid
+        .spot(move_.source)
+        .expect("apply_move source should be a valid grid point");
+    update_start_endpoint(grid, source, on_event);
 
     let axis = move_.direction.axis();
-    let mut ray_coord = move_.source + move_.direction;
-    while let Some(space) = grid.get_mut_vc(ray_coord) {
-        match space {
+    let mut end = source;
+    for spot in grid.ray_from(source, move_.direction) {
+        match grid.space(spot) {
             GridSpace::Empty => {
-                *space = GridSpace::LineSegment {
+                grid.set_space(
+                    spot,
+                    GridSpace::LineSegment {
+                        axis,
+                        hardened: false,
+                    },
+                );
+
+                on_event(MoveEvent::LineDrawn {
+                    at: spot.coord,
                     axis,
-                    hardened: false,
-                };
+                });
             }
 
-            GridSpace::LineSegment { axis: ax, hardened } => {
-                if *hardened {
+            GridSpace::LineSegment {
+                axis: crossed_axis,
+                hardened,
+            } => {
+                if hardened {
                     break;
                 }
 
-                let (dir1, dir2) = ax.directions();
+                let (dir1, dir2) = crossed_axis.directions();
+
+                grid.set_space(
+                    spot,
+                    GridSpace::LineSegment {
+                        axis,
+                        hardened: true,
+                    },
+                );
 
-                *space = GridSpace::LineSegment {
+                on_event(MoveEvent::Hardened {
+                    at: spot.coord,
                     axis,
-                    hardened: true,
-                };
+                    crossed_axis,
+                });
 
-                cut_line_in_direction(grid, move_cache, ray_coord + dir1, dir1);
-                cut_line_in_direction(grid, move_cache, ray_coord + dir2, dir2);
+                cut_line_in_direction(grid, spot, dir1, on_event);
+                cut_line_in_direction(grid, spot, dir2, on_event);
             }
 
             GridSpace::Blocked | GridSpace::Endpoint { .. } => break,
         }
 
-        ray_coord += move_.direction;
+        end = spot;
     }
 
-    let end_coord = ray_coord - move_.direction;
-    create_end_endpoint(grid, end_coord, player);
-    move_cache.add_endpoint(player, end_coord);
-}
+    grid.set_space(
+        end,
+        GridSpace::Endpoint {
+            owner: player,
+            connected_lines: 1,
+        },
+    );
 
-fn update_start_endpoint(grid: &mut Grid, coord: VirtD3) {
-    let mut gs = grid
-        .get_mut_vc(coord)
-        .expect("apply_move source should be a valid grid point");
+    on_event(MoveEvent::EndpointPlaced {
+        at: end.coord,
+        owner: player,
+    });
+}
 
+fn update_start_endpoint(
+    grid: &mut impl RayGrid,
+    source: Spot,
+    on_event: &mut impl FnMut(MoveEvent),
+) {
     if let GridSpace::Endpoint {
-        connected_lines, ..
-    } = gs
+        owner,
+        connected_lines,
+    } = grid.space(source)
     {
-        *connected_lines += 1;
+        let connected_lines = connected_lines + 1;
+        grid.set_space(
+            source,
+            GridSpace::Endpoint {
+                owner,
+                connected_lines,
+            },
+        );
+
+        on_event(MoveEvent::SourceConnected {
+            at: source.coord,
+            connected_lines,
+        });
     } else {
         panic!("can only apply_move if source is an endpoint");
     }
 }
 
-fn create_end_endpoint(grid: &mut Grid, coord: VirtD3, player: SpicePlayer) {
-    let endpoint = GridSpace::Endpoint {
-        owner: player,
-        connected_lines: 1,
-    };
-
-    grid.set_vc(coord, endpoint)
-        .expect("should be able to set the end endpoint");
-}
-
+/// Cuts the line through `crossing`, from the space after it in `dir` on.
 fn cut_line_in_direction(
-    grid: &mut Grid,
-    move_cache: &mut MoveCache,
-    start_coord: VirtD3,
+    grid: &mut impl RayGrid,
+    crossing: Spot,
     dir: Direction,
+    on_event: &mut impl FnMut(MoveEvent),
 ) {
-    let mut coord = start_coord;
-    while let Some(space) = grid.get_mut_vc(coord) {
-        match space {
+    for spot in grid.ray_from(crossing, dir) {
+        match grid.space(spot) {
             GridSpace::LineSegment { .. } => {
-                *space = GridSpace::Empty;
+                grid.set_space(spot, GridSpace::Empty);
+
+                on_event(MoveEvent::SegmentCut {
+                    at: spot.coord,
+                    direction: dir,
+                });
             }
 
             GridSpace::Endpoint {
                 owner,
                 connected_lines,
             } => {
-                if *connected_lines > 1 {
-                    *connected_lines -= 1;
+                if connected_lines > 1 {
+                    let connected_lines = connected_lines - 1;
+                    grid.set_space(
+                        spot,
+                        GridSpace::Endpoint {
+                            owner,
+                            connected_lines,
+                        },
+                    );
+
+                    on_event(MoveEvent::EndpointDisconnected {
+                        at: spot.coord,
+                        owner,
+                        connected_lines,
+                    });
                 } else {
-                    move_cache.remove_endpoint(*owner, coord);
-                    *space = GridSpace::Blocked;
+                    grid.set_space(spot, GridSpace::Blocked);
+
+                    on_event(MoveEvent::EndpointBlocked {
+                        at: spot.coord,
+                        owner,
+                    });
                 };
 
                 break;
@@ -191,8 +603,6 @@ fn cut_line_in_direction(
 
             _ => panic!("should only see lines or endpoints when clearing a line"),
         }
-
-        coord += dir;
     }
 }
 
@@ -407,8 +817,7 @@ mod tests {
             empty_grid.set_vc_unchecked(index, value);
         }
 
-        let move_cache = MoveCache::from_grid(&empty_grid);
-        let mut actual_moves = generate_moves(&empty_grid, player, &move_cache);
+        let mut actual_moves = generate_moves(&empty_grid, player);
 
         sort_move_list(&mut actual_moves);
         sort_move_list(&mut expected_moves);
@@ -0,0 +1,534 @@
+//! Text notation for Spice positions, in the spirit of chess's FEN. A position is five
+//! fields separated by single spaces:
+//!
+//! ```text
+//! 5.2 -3,-3,-3:R0;3,3,3:B0 b 0 400
+//! ```
+//!
+//! 1. The board's radius.
+//! 2. Every space that isn't empty, as `i,j,k:code`, separated by `;`, or `-` if every
+//!    space is empty. The codes are `X` for a blocked space, `B<n>` or `R<n>` for a Blue or
+//!    Red endpoint with `n` connected lines, and an axis name (like `NeSw`) for a line,
+//!    followed by `+` if the line is hardened.
+//! 3. The player to move, `b` or `r`.
+//! 4. How many moves have been played.
+//! 5. How many moves can be played before the game is a draw.
+//!
+//! Moves are written as their source coordinate and a two-letter direction, like
+//! `3,3,3:DS`. The long form adds where the new endpoint ends up, and every point where
+//! the move cuts through a line: `3,3,3:DS>-3,3,3x0,3,3`.
+
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+use super::{config::*, coord::*, direction::*, grid::*, moves::*, players::*, SpiceState};
+
+const AXIS_NAMES: [(Axis, &str); 6] = [
+    (Axis::NeSw, "NeSw"),
+    (Axis::NwSe, "NwSe"),
+    (Axis::UnDs, "UnDs"),
+    (Axis::UsDn, "UsDn"),
+    (Axis::UeDw, "UeDw"),
+    (Axis::UwDe, "UwDe"),
+];
+
+const DIRECTION_NAMES: [(Direction, &str); 12] = [
+    (Direction::NorthEast, "NE"),
+    (Direction::NorthWest, "NW"),
+    (Direction::SouthEast, "SE"),
+    (Direction::SouthWest, "SW"),
+    (Direction::UpNorth, "UN"),
+    (Direction::UpSouth, "US"),
+    (Direction::UpEast, "UE"),
+    (Direction::UpWest, "UW"),
+    (Direction::DownNorth, "DN"),
+    (Direction::DownSouth, "DS"),
+    (Direction::DownEast, "DE"),
+    (Direction::DownWest, "DW"),
+];
+
+/// A move along with its effects in a particular position, for writing the long form.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct LongMove {
+    pub move_: SpiceMove,
+    /// Where the move's new endpoint is placed.
+    pub end: VirtD3,
+    /// Every space where the move crosses, and cuts, an existing line.
+    pub cuts: Vec<VirtD3>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum MoveParseError {
+    /// The move wasn't written as `i,j,k:DIR`, with an optional long form suffix.
+    Malformed,
+    /// A coordinate wasn't three comma-separated integers.
+    InvalidCoordinate,
+    /// The direction wasn't a two-letter abbreviation like `NE` or `DS`.
+    InvalidDirection,
+    /// The move can't be played in the position it was parsed for.
+    Illegal(SpiceRuleError),
+    /// The long form's end or cuts don't match what the move does.
+    LongFormMismatch,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct PositionParseError {
+    /// Byte offset into the notation where the problem starts.
+    pub column: usize,
+    pub kind: PositionErrorKind,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PositionErrorKind {
+    /// The notation didn't have exactly five fields. Holds how many it had.
+    FieldCount(usize),
+    /// The radius wasn't a number between 1 and [MAX_RADIUS].
+    InvalidRadius,
+    /// A space wasn't written as `i,j,k:code`.
+    MalformedSpace,
+    /// A coordinate wasn't three comma-separated integers.
+    InvalidCoordinate,
+    /// A coordinate was outside the board.
+    OutOfBounds(VirtD3),
+    /// The same coordinate was listed twice.
+    DuplicateSpace(VirtD3),
+    /// A space's code wasn't one of the recognized codes.
+    InvalidSpaceCode,
+    /// The center can't be blocked.
+    CenterBlocked,
+    /// The player to move wasn't `b` or `r`.
+    InvalidPlayer,
+    InvalidMoveCount,
+    InvalidMaxMoves,
+}
+
+impl Display for SpiceState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let spaces: Vec<String> = self
+            .grid
+            .enumerate_vc()
+            .filter(|(_, s)| **s != GridSpace::Empty)
+            .map(|(c, s)| format!("{}:{}", format_coord(c), format_space(s)))
+            .collect();
+
+        let spaces = if spaces.is_empty() {
+            "-".to_owned()
+        } else {
+            spaces.join(";")
+        };
+
+        let player = match self.player {
+            SpicePlayer::Blue => 'b',
+            SpicePlayer::Red => 'r',
+        };
+
+        write!(
+            f,
+            "{} {spaces} {player} {} {}",
+            self.grid.radius(),
+            self.move_count,
+            self.max_moves
+        )
+    }
+}
+
+/// Parses the notation written by [SpiceState]'s [Display] implementation.
+impl FromStr for SpiceState {
+    type Err = PositionParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim_end();
+        let fields: Vec<&str> = s.split(' ').collect();
+
+        let [radius, spaces, player, move_count, max_moves] = fields[..] else {
+            return Err(PositionParseError {
+                column: 0,
+                kind: PositionErrorKind::FieldCount(fields.len()),
+            });
+        };
+
+        let error = |part: &str, kind| PositionParseError {
+            column: offset_in(s, part),
+            kind,
+        };
+
+        let mut grid = parse_number(radius)
+            .and_then(Grid::try_with_radius)
+            .ok_or_else(|| error(radius, PositionErrorKind::InvalidRadius))?;
+
+        if spaces != "-" {
+            for entry in spaces.split(';') {
+                let (coord, space) = parse_entry(entry).map_err(|kind| error(entry, kind))?;
+
+                match grid.get_vc(coord) {
+                    None => return Err(error(entry, PositionErrorKind::OutOfBounds(coord))),
+                    Some(GridSpace::Empty) => grid.set_vc_unchecked(coord, space),
+                    Some(_) => return Err(error(entry, PositionErrorKind::DuplicateSpace(coord))),
+                }
+            }
+        }
+
+        let player = match player {
+            "b" => SpicePlayer::Blue,
+            "r" => SpicePlayer::Red,
+            _ => return Err(error(player, PositionErrorKind::InvalidPlayer)),
+        };
+
+        let move_count = parse_number(move_count)
+            .ok_or_else(|| error(move_count, PositionErrorKind::InvalidMoveCount))?;
+
+        let max_moves = parse_number(max_moves)
+            .ok_or_else(|| error(max_moves, PositionErrorKind::InvalidMaxMoves))?;
+
+        let move_cache = MoveCache::from_grid(&grid);
+
+        Ok(Self {
+            grid,
+            player,
+            move_cache,
+            move_count,
+            max_moves,
+        })
+    }
+}
+
+impl Display for SpiceMove {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let direction = DIRECTION_NAMES
+            .iter()
+            .find(|(d, _)| *d == self.direction)
+            .unwrap()
+            .1;
+
+        write!(f, "{}:{direction}", format_coord(self.source))
+    }
+}
+
+/// Parses the short form written by [SpiceMove]'s [Display] implementation. Use
+/// [SpiceState::parse_move] to also accept the long form, and check the move is legal.
+impl FromStr for SpiceMove {
+    type Err = MoveParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (source, direction) = s.split_once(':').ok_or(MoveParseError::Malformed)?;
+
+        let source = parse_coord(source).ok_or(MoveParseError::InvalidCoordinate)?;
+        let direction = DIRECTION_NAMES
+            .iter()
+            .find(|(_, n)| *n == direction)
+            .ok_or(MoveParseError::InvalidDirection)?
+            .0;
+
+        Ok(Self { source, direction })
+    }
+}
+
+impl Display for LongMove {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}>{}", self.move_, format_coord(self.end))?;
+
+        for &cut in &self.cuts {
+            write!(f, "x{}", format_coord(cut))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl SpiceState {
+    /// Works out where `move_` would end and what it would cut, if it's legal.
+    pub fn long_move(&self, move_: &SpiceMove) -> Result<LongMove, SpiceRuleError> {
+        let preview = self.preview_move(move_)?;
+
+        Ok(LongMove {
+            move_: move_.clone(),
+            end: preview.end,
+            cuts: preview.cuts,
+        })
+    }
+
+    /// Parses a move in either the short or long form, and checks it can be played here.
+    pub fn parse_move(&self, s: &str) -> Result<SpiceMove, MoveParseError> {
+        let (short, long) = match s.split_once('>') {
+            Some((short, long)) => (short, Some(long)),
+            None => (s, None),
+        };
+
+        let move_: SpiceMove = short.parse()?;
+        let expected = self.long_move(&move_).map_err(MoveParseError::Illegal)?;
+
+        if let Some(long) = long {
+            let mut coords = long.split('x').map(parse_coord);
+
+            let end = coords.next().flatten();
+            let cuts: Option<Vec<VirtD3>> = coords.collect();
+            let cuts = cuts.ok_or(MoveParseError::InvalidCoordinate)?;
+
+            if end.ok_or(MoveParseError::InvalidCoordinate)? != expected.end
+                || cuts != expected.cuts
+            {
+                return Err(MoveParseError::LongFormMismatch);
+            }
+        }
+
+        Ok(move_)
+    }
+}
+
+fn parse_entry(entry: &str) -> Result<(VirtD3, GridSpace), PositionErrorKind> {
+    let (coord, code) = entry
+        .split_once(':')
+        .ok_or(PositionErrorKind::MalformedSpace)?;
+
+    let coord = parse_coord(coord).ok_or(PositionErrorKind::InvalidCoordinate)?;
+    let space = parse_space(code).ok_or(PositionErrorKind::InvalidSpaceCode)?;
+
+    if coord == virt_d3(0, 0, 0) && space == GridSpace::Blocked {
+        return Err(PositionErrorKind::CenterBlocked);
+    }
+
+    Ok((coord, space))
+}
+
+pub(super) fn format_coord(coord: VirtD3) -> String {
+    let VirtD3 { i, j, k } = coord;
+    format!("{i},{j},{k}")
+}
+
+pub(super) fn parse_coord(s: &str) -> Option<VirtD3> {
+    let mut components = s.split(',').map(parse_number::<i8>);
+
+    match (
+        components.next(),
+        components.next(),
+        components.next(),
+        components.next(),
+    ) {
+        (Some(Some(i)), Some(Some(j)), Some(Some(k)), None) => Some(virt_d3(i, j, k)),
+        _ => None,
+    }
+}
+
+/// Like [str::parse], but without allowing a leading `+`, so that there's only one way to
+/// write each number.
+fn parse_number<T: FromStr>(s: &str) -> Option<T> {
+    if s.starts_with('+') {
+        None
+    } else {
+        s.parse().ok()
+    }
+}
+
+fn format_space(space: &GridSpace) -> String {
+    match space {
+        GridSpace::Empty => "-".to_owned(),
+        GridSpace::Blocked => "X".to_owned(),
+        GridSpace::LineSegment { axis, hardened } => {
+            let name = AXIS_NAMES.iter().find(|(a, _)| a == axis).unwrap().1;
+            format!("{name}{}", if *hardened { "+" } else { "" })
+        }
+        GridSpace::Endpoint {
+            owner,
+            connected_lines,
+        } => {
+            let owner = match owner {
+                SpicePlayer::Blue => 'B',
+                SpicePlayer::Red => 'R',
+            };
+            format!("{owner}{connected_lines}")
+        }
+    }
+}
+
+fn parse_space(code: &str) -> Option<GridSpace> {
+    if code == "X" {
+        return Some(GridSpace::Blocked);
+    }
+
+    if let Some(lines) = code.strip_prefix('B') {
+        return Some(GridSpace::Endpoint {
+            owner: SpicePlayer::Blue,
+            connected_lines: parse_number(lines)?,
+        });
+    }
+
+    if let Some(lines) = code.strip_prefix('R') {
+        return Some(GridSpace::Endpoint {
+            owner: SpicePlayer::Red,
+            connected_lines: parse_number(lines)?,
+        });
+    }
+
+    let (name, hardened) = match code.strip_suffix('+') {
+        Some(name) => (name, true),
+        None => (code, false),
+    };
+
+    AXIS_NAMES
+        .iter()
+        .find(|(_, n)| *n == name)
+        .map(|&(axis, _)| GridSpace::LineSegment { axis, hardened })
+}
+
+/// Where `part`, which must be a slice of `whole`, starts within it.
+fn offset_in(whole: &str, part: &str) -> usize {
+    part.as_ptr() as usize - whole.as_ptr() as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use mcts::GameState;
+    use rstest::*;
+
+    use super::*;
+    use crate::spice::fixtures::*;
+
+    const INITIAL: &str = "5.2 -3,-3,-3:R0;3,3,3:B0 b 0 400";
+
+    #[rstest]
+    fn initial_state_notation() {
+        assert_eq!(SpiceState::initial_state().to_string(), INITIAL);
+        assert_eq!(INITIAL.parse(), Ok(SpiceState::initial_state()));
+    }
+
+    #[rstest]
+    fn played_positions_round_trip(small_state: SpiceState) {
+        let mut state = small_state;
+
+        while state.terminal_value(state.player).is_none() {
+            let notation = state.to_string();
+            let parsed: SpiceState = notation.parse().unwrap();
+
+            assert_eq!(parsed.to_string(), notation);
+            assert_eq!(parsed.grid, state.grid);
+
+            let move_ = state.available_moves().next().unwrap();
+            state = state.apply_move(&move_);
+        }
+    }
+
+    #[rstest]
+    fn every_space_code_round_trips() {
+        let notation = "2 0,0,-1:B255;0,0,1:R3;0,1,0:UwDe+;1,0,0:NeSw;1,1,0:X r 12 20";
+        let state: SpiceState = notation.parse().unwrap();
+
+        assert_eq!(state.to_string(), notation);
+        assert_eq!(
+            state.grid.get_vc(virt_d3(0, 1, 0)),
+            Some(&GridSpace::LineSegment {
+                axis: Axis::UwDe,
+                hardened: true
+            })
+        );
+        assert_eq!(state.move_count, 12);
+        assert_eq!(state.max_moves, 20);
+    }
+
+    #[rstest]
+    fn empty_boards_use_a_dash() {
+        let notation = "1.5 - r 0 1";
+        let state: SpiceState = notation.parse().unwrap();
+
+        assert_eq!(state.to_string(), notation);
+    }
+
+    #[rstest]
+    fn every_move_round_trips() {
+        for direction in Direction::ALL {
+            let move_ = SpiceMove::new(virt_d3(-1, 2, 0), direction);
+            assert_eq!(move_.to_string().parse(), Ok(move_));
+        }
+
+        assert_eq!(
+            SpiceMove::new(virt_d3(3, 3, 3), Direction::DownSouth).to_string(),
+            "3,3,3:DS"
+        );
+    }
+
+    #[rstest]
+    #[case("3,3,3DS", MoveParseError::Malformed)]
+    #[case("3,3:DS", MoveParseError::InvalidCoordinate)]
+    #[case("3,3,300:DS", MoveParseError::InvalidCoordinate)]
+    #[case("+3,3,3:DS", MoveParseError::InvalidCoordinate)]
+    #[case("3,3,3:ds", MoveParseError::InvalidDirection)]
+    #[case("3,3,3:DownSouth", MoveParseError::InvalidDirection)]
+    fn malformed_moves_are_rejected(#[case] notation: &str, #[case] error: MoveParseError) {
+        assert_eq!(notation.parse::<SpiceMove>(), Err(error));
+    }
+
+    #[rstest]
+    fn long_form_shows_end_and_cuts() {
+        let state: SpiceState =
+            "5.2 0,3,2:R2;0,3,3:NeSw;0,3,4:R1;1,3,2:UnDs;2,3,2:R1;3,3,3:B0 b 0 400"
+                .parse()
+                .unwrap();
+        assert_eq!(state.validate(), vec![]);
+        let move_ = "3,3,3:DS".parse().unwrap();
+
+        let long = state.long_move(&move_).unwrap();
+        assert_eq!(long.to_string(), "3,3,3:DS>-3,3,3x0,3,3");
+        assert_eq!(state.parse_move(&long.to_string()), Ok(move_.clone()));
+        assert_eq!(state.parse_move("3,3,3:DS"), Ok(move_));
+    }
+
+    #[rstest]
+    #[case("-3,-3,-3:UN", MoveParseError::Illegal(SpiceRuleError::NotYourEndpoint(virt_d3(-3, -3, -3))))]
+    #[case(
+        "0,0,0:UN",
+        MoveParseError::Illegal(SpiceRuleError::NotAnEndpoint(virt_d3(0, 0, 0)))
+    )]
+    #[case("3,3,3:UN", MoveParseError::Illegal(SpiceRuleError::DirectionBlocked))]
+    #[case("3,3,3:DS>-1,3,3", MoveParseError::LongFormMismatch)]
+    #[case("3,3,3:DS>-3,3,3x1,3,3", MoveParseError::LongFormMismatch)]
+    #[case("3,3,3:DS>-3,3,3x", MoveParseError::InvalidCoordinate)]
+    fn moves_are_validated_against_the_position(
+        #[case] notation: &str,
+        #[case] error: MoveParseError,
+    ) {
+        let state = SpiceState::initial_state();
+        assert_eq!(state.parse_move(notation), Err(error));
+    }
+
+    #[rstest]
+    #[case("5.2 - b 0", 0, PositionErrorKind::FieldCount(4))]
+    #[case("5.2  - b 0 400", 0, PositionErrorKind::FieldCount(6))]
+    #[case("big - b 0 400", 0, PositionErrorKind::InvalidRadius)]
+    #[case("0.5 - b 0 400", 0, PositionErrorKind::InvalidRadius)]
+    #[case("+5.2 - b 0 400", 0, PositionErrorKind::InvalidRadius)]
+    #[case("5.2 3,3,3:B0;3,3,3 b 0 400", 13, PositionErrorKind::MalformedSpace)]
+    #[case("5.2 3,3:B0 b 0 400", 4, PositionErrorKind::InvalidCoordinate)]
+    #[case("5.2 3,3,3,3:B0 b 0 400", 4, PositionErrorKind::InvalidCoordinate)]
+    #[case("5.2 3,3,3:Q b 0 400", 4, PositionErrorKind::InvalidSpaceCode)]
+    #[case("5.2 3,3,3:B b 0 400", 4, PositionErrorKind::InvalidSpaceCode)]
+    #[case("5.2 3,3,3:B+0 b 0 400", 4, PositionErrorKind::InvalidSpaceCode)]
+    #[case("5.2 3,+3,3:B0 b 0 400", 4, PositionErrorKind::InvalidCoordinate)]
+    #[case("5.2 3,3,3:NeSw- b 0 400", 4, PositionErrorKind::InvalidSpaceCode)]
+    #[case(
+        "5.2 5,5,5:X b 0 400",
+        4,
+        PositionErrorKind::OutOfBounds(virt_d3(5, 5, 5))
+    )]
+    #[case(
+        "5.2 1,1,1:X;1,1,1:B0 b 0 400",
+        12,
+        PositionErrorKind::DuplicateSpace(virt_d3(1, 1, 1))
+    )]
+    #[case("5.2 0,0,0:X b 0 400", 4, PositionErrorKind::CenterBlocked)]
+    #[case("5.2 - blue 0 400", 6, PositionErrorKind::InvalidPlayer)]
+    #[case("5.2 - b -1 400", 8, PositionErrorKind::InvalidMoveCount)]
+    #[case("5.2 - b +0 400", 8, PositionErrorKind::InvalidMoveCount)]
+    #[case("5.2 - b 0 +400", 10, PositionErrorKind::InvalidMaxMoves)]
+    #[case("5.2 - b 0 lots", 10, PositionErrorKind::InvalidMaxMoves)]
+    fn errors_point_at_the_problem(
+        #[case] notation: &str,
+        #[case] column: usize,
+        #[case] kind: PositionErrorKind,
+    ) {
+        assert_eq!(
+            notation.parse::<SpiceState>(),
+            Err(PositionParseError { column, kind })
+        );
+    }
+}
@@ -0,0 +1,324 @@
+//! Opening book: deep searches of the first few plies of Spice, done offline and saved to
+//! a file, so that the AI doesn't have to rediscover the same opening lines every game.
+
+use std::{cmp::Reverse, collections::HashMap, fs, io, path::Path};
+
+use mcts::{GameState, SearchParameters, Searcher};
+use rand::{seq::SliceRandom, Rng};
+use serde::{Deserialize, Serialize};
+
+use super::{grid::*, moves::*, players::*, SpiceState};
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct BookMove {
+    pub move_: SpiceMove,
+    /// Relative likelihood of playing this move, when choosing randomly.
+    pub weight: f32,
+    /// The search's estimate of this move, for the player making it.
+    pub value: f32,
+}
+
+/// Book moves are keyed by position rather than by the line that led there, so that
+/// transpositions share an entry.
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
+#[serde(into = "Vec<BookEntry>", try_from = "Vec<BookEntry>")]
+pub struct OpeningBook {
+    positions: HashMap<BookKey, BookPosition>,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+struct BookKey {
+    grid: Grid,
+    player: SpicePlayer,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+struct BookPosition {
+    line: Vec<SpiceMove>,
+    moves: Vec<BookMove>,
+}
+
+/// How a position is stored on disk: the moves from [SpiceState::initial_state] that
+/// reach it, which are replayed on load to rebuild the key.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+struct BookEntry {
+    line: Vec<SpiceMove>,
+    moves: Vec<BookMove>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BookParameters {
+    /// How many plies from the start of the game the book covers.
+    pub plies: u16,
+    /// How many of the best moves from each position get expanded into the next ply.
+    pub width: usize,
+    pub search: SearchParameters,
+}
+
+impl OpeningBook {
+    /// The book moves for `state`, or [None] if the game is out of book.
+    pub fn moves(&self, state: &SpiceState) -> Option<&[BookMove]> {
+        self.positions
+            .get(&BookKey::new(state))
+            .map(|p| p.moves.as_slice())
+    }
+
+    /// Picks a book move for `state`: randomly by weight if `randomize` is set, or the
+    /// heaviest move otherwise.
+    pub fn choose_move(
+        &self,
+        state: &SpiceState,
+        randomize: bool,
+        rng: &mut impl Rng,
+    ) -> Option<SpiceMove> {
+        let moves = self.moves(state)?;
+
+        let chosen = if randomize {
+            moves.choose_weighted(rng, |m| m.weight).ok()
+        } else {
+            moves.iter().max_by(|a, b| a.weight.total_cmp(&b.weight))
+        };
+
+        chosen.map(|m| m.move_.clone())
+    }
+
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(serde_json::from_slice(&fs::read(path)?)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, serde_json::to_vec(self)?)
+    }
+
+    fn insert(&mut self, state: &SpiceState, line: Vec<SpiceMove>, moves: Vec<BookMove>) {
+        self.positions
+            .insert(BookKey::new(state), BookPosition { line, moves });
+    }
+}
+
+/// Builds a book by searching every position reachable within `parameters.plies` plies of
+/// the start, following only the `parameters.width` most visited moves from each.
+pub fn generate_opening_book(parameters: &BookParameters) -> OpeningBook {
+    let mut book = OpeningBook::default();
+    let mut frontier = vec![(SpiceState::initial_state(), Vec::new())];
+
+    for ply in 0..parameters.plies {
+        let mut next_frontier = Vec::new();
+
+        for (state, line) in frontier {
+            if state.is_over() || book.moves(&state).is_some() {
+                continue;
+            }
+
+            let moves = search_book_moves(&state, parameters);
+
+            if ply + 1 < parameters.plies {
+                for book_move in &moves {
+                    let mut child_line = line.clone();
+                    child_line.push(book_move.move_.clone());
+                    next_frontier.push((state.apply_move(&book_move.move_), child_line));
+                }
+            }
+
+            book.insert(&state, line, moves);
+        }
+
+        frontier = next_frontier;
+    }
+
+    book
+}
+
+fn search_book_moves(state: &SpiceState, parameters: &BookParameters) -> Vec<BookMove> {
+    let mut searcher = Searcher::new(parameters.search.clone());
+    let chosen = searcher.search(state.clone());
+
+    if let Some(solution) = searcher.last_solution() {
+        // the solver proved this move, so there's nothing else worth playing
+        return vec![BookMove {
+            move_: chosen,
+            weight: 1.,
+            value: solution.value,
+        }];
+    }
+
+    let stats = searcher
+        .root_statistics()
+        .expect("a search that didn't play a proven move should have built a tree");
+
+    let mut children = stats.children;
+    children.sort_by_key(|c| Reverse(c.visits));
+    children.truncate(parameters.width);
+
+    let total_visits: i32 = children.iter().map(|c| c.visits).sum();
+
+    children
+        .into_iter()
+        .map(|c| BookMove {
+            weight: c.visits as f32 / total_visits.max(1) as f32,
+            value: c.score / c.visits.max(1) as f32,
+            move_: c.move_,
+        })
+        .collect()
+}
+
+impl BookKey {
+    fn new(state: &SpiceState) -> Self {
+        Self {
+            grid: state.grid.clone(),
+            player: state.player,
+        }
+    }
+}
+
+impl From<OpeningBook> for Vec<BookEntry> {
+    fn from(book: OpeningBook) -> Self {
+        let mut entries: Vec<BookEntry> = book
+            .positions
+            .into_values()
+            .map(|p| BookEntry {
+                line: p.line,
+                moves: p.moves,
+            })
+            .collect();
+
+        // shallowest first, so that files read in the same order as the game
+        entries.sort_by_key(|e| e.line.len());
+        entries
+    }
+}
+
+impl TryFrom<Vec<BookEntry>> for OpeningBook {
+    type Error = String;
+
+    fn try_from(entries: Vec<BookEntry>) -> Result<Self, Self::Error> {
+        let mut book = OpeningBook::default();
+
+        for entry in entries {
+            let mut state = SpiceState::initial_state();
+
+            for move_ in &entry.line {
+                if !state.available_moves().any(|m| m == *move_) {
+                    return Err(format!(
+                        "book line {:?} contains an illegal move",
+                        entry.line
+                    ));
+                }
+
+                state = state.apply_move(move_);
+            }
+
+            book.insert(&state, entry.line, entry.moves);
+        }
+
+        Ok(book)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+    use rstest::*;
+
+    use super::*;
+
+    #[fixture]
+    fn parameters() -> BookParameters {
+        BookParameters {
+            plies: 2,
+            width: 2,
+            search: SearchParameters {
+                exploration_factor: std::f32::consts::FRAC_1_SQRT_2,
+                search_iterations: 4,
+                solver_node_budget: None,
+                rollout_cutoff: None,
+            },
+        }
+    }
+
+    #[fixture]
+    fn book(parameters: BookParameters) -> OpeningBook {
+        generate_opening_book(&parameters)
+    }
+
+    #[rstest]
+    fn proven_draws_are_booked_as_draws(mut parameters: BookParameters) {
+        // every move uses up the last one the game allows
+        let state: SpiceState = "5.2 3,3,3:B0;-3,-3,-3:R0 b 399 400".parse().unwrap();
+        parameters.search.solver_node_budget = Some(10_000);
+
+        let moves = search_book_moves(&state, &parameters);
+
+        assert_eq!(moves.len(), 1);
+        assert_eq!(moves[0].value, 0.);
+    }
+
+    #[rstest]
+    fn book_covers_requested_plies(book: OpeningBook) {
+        let initial = SpiceState::initial_state();
+        let moves = book.moves(&initial).unwrap();
+
+        assert!(!moves.is_empty() && moves.len() <= 2);
+        for m in moves {
+            assert!(book.moves(&initial.apply_move(&m.move_)).is_some());
+        }
+
+        assert!(book.len() <= 3);
+    }
+
+    #[rstest]
+    fn book_is_out_of_book_past_its_plies(book: OpeningBook) {
+        let mut state = SpiceState::initial_state();
+        for _ in 0..2 {
+            let move_ = book.moves(&state).unwrap()[0].move_.clone();
+            state = state.apply_move(&move_);
+        }
+
+        assert_eq!(book.moves(&state), None);
+        assert_eq!(
+            book.choose_move(&state, true, &mut StdRng::seed_from_u64(0)),
+            None
+        );
+    }
+
+    #[rstest]
+    fn choose_move_picks_book_moves(book: OpeningBook) {
+        let initial = SpiceState::initial_state();
+        let moves = book.moves(&initial).unwrap();
+        let mut rng = StdRng::seed_from_u64(0);
+
+        let heaviest = book.choose_move(&initial, false, &mut rng).unwrap();
+        let max_weight = moves.iter().map(|m| m.weight).fold(0., f32::max);
+        assert!(moves
+            .iter()
+            .any(|m| m.move_ == heaviest && m.weight == max_weight));
+
+        for _ in 0..10 {
+            let chosen = book.choose_move(&initial, true, &mut rng).unwrap();
+            assert!(moves.iter().any(|m| m.move_ == chosen));
+        }
+    }
+
+    #[rstest]
+    fn book_round_trips_through_json(book: OpeningBook) {
+        let json = serde_json::to_string(&book).unwrap();
+        let loaded: OpeningBook = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(loaded, book);
+    }
+
+    #[rstest]
+    fn loading_rejects_illegal_lines() {
+        let json = r#"[{"line": [{"source": {"i": 0, "j": 0, "k": 0}, "direction": "UpNorth"}], "moves": []}]"#;
+
+        assert!(serde_json::from_str::<OpeningBook>(json).is_err());
+    }
+}
@@ -1,5 +1,16 @@
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
 pub enum SpicePlayer {
     Red,
     Blue,
 }
+
+impl SpicePlayer {
+    pub fn opponent(self) -> Self {
+        match self {
+            SpicePlayer::Red => SpicePlayer::Blue,
+            SpicePlayer::Blue => SpicePlayer::Red,
+        }
+    }
+}
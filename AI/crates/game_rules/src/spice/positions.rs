@@ -0,0 +1,269 @@
+//! Random positions from the middle and end of games, for benchmarks, fuzzing and finding
+//! puzzles. Positions are reached by playing legal moves from the start, so they look like
+//! ones from real games rather than grids filled in at random.
+
+use mcts::GameState;
+use rand::{rngs::StdRng, seq::IteratorRandom, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use super::{evaluation::*, hints::can_capture_center, players::*, SpiceState};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PositionParameters {
+    /// When to stop playing moves and consider the position.
+    pub target: PositionTarget,
+    pub policy: MovePolicy,
+    /// Conditions every generated position has to meet.
+    pub filters: Vec<PositionFilter>,
+    /// How many games to play looking for a position that passes the filters, before giving
+    /// up.
+    pub max_attempts: u32,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum PositionTarget {
+    /// Stop after this many moves.
+    MoveCount(u16),
+    /// Stop once at least this fraction of the grid's spaces, between 0 and 1, are endpoints.
+    EndpointDensity(f32),
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub enum MovePolicy {
+    /// Every legal move is equally likely.
+    Random,
+    /// Plays whichever of `candidates` random legal moves `evaluator` likes best for the
+    /// player making it, so games look a bit less aimless. At least one move is always
+    /// considered, even if `candidates` is 0.
+    Guided {
+        evaluator: LinearEvaluator,
+        candidates: usize,
+    },
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum PositionFilter {
+    /// The game isn't over.
+    NonTerminal,
+    /// It's this player's turn.
+    ToMove(SpicePlayer),
+    /// The player to move can take the center with their next move.
+    CenterReachable,
+}
+
+/// Generates positions by playing games with [PositionParameters::policy] until they hit
+/// [PositionParameters::target], keeping the ones that pass every filter. The same seed
+/// always gives the same positions.
+///
+/// Runs out once [PositionParameters::max_attempts] games in a row are filtered out.
+pub struct PositionGenerator {
+    parameters: PositionParameters,
+    rng: StdRng,
+}
+
+impl PositionGenerator {
+    pub fn new(parameters: PositionParameters, seed: u64) -> Self {
+        Self {
+            parameters,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    fn play_game(&mut self) -> SpiceState {
+        let mut state = SpiceState::initial_state();
+        let spaces = state.grid.enumerate_vc().count() as f32;
+
+        loop {
+            let reached = match self.parameters.target {
+                PositionTarget::MoveCount(count) => state.move_count >= count,
+                PositionTarget::EndpointDensity(density) => {
+                    let endpoints = [SpicePlayer::Blue, SpicePlayer::Red]
+                        .map(|p| state.move_cache.endpoint_coords(p).len())
+                        .iter()
+                        .sum::<usize>();
+
+                    endpoints as f32 / spaces >= density
+                }
+            };
+
+            if reached {
+                return state;
+            }
+
+            match self.choose_move(&state) {
+                Some(next) => state = next,
+                None => return state,
+            }
+        }
+    }
+
+    /// The position after the move the policy picks, or [None] if the game is over.
+    fn choose_move(&mut self, state: &SpiceState) -> Option<SpiceState> {
+        match &self.parameters.policy {
+            MovePolicy::Random => state
+                .available_moves()
+                .choose(&mut self.rng)
+                .map(|m| state.apply_move(&m)),
+            MovePolicy::Guided {
+                evaluator,
+                candidates,
+            } => state
+                .available_moves()
+                .choose_multiple(&mut self.rng, (*candidates).max(1))
+                .into_iter()
+                .map(|m| state.apply_move(&m))
+                .max_by(|a, b| {
+                    let value = |s: &SpiceState| evaluator.value(s, state.player);
+                    value(a).total_cmp(&value(b))
+                }),
+        }
+    }
+
+    fn passes_filters(&self, state: &SpiceState) -> bool {
+        self.parameters.filters.iter().all(|filter| match filter {
+            PositionFilter::NonTerminal => state.terminal_value(state.player).is_none(),
+            PositionFilter::ToMove(player) => state.player == *player,
+            PositionFilter::CenterReachable => can_capture_center(state, state.player),
+        })
+    }
+}
+
+impl Iterator for PositionGenerator {
+    type Item = SpiceState;
+
+    fn next(&mut self) -> Option<SpiceState> {
+        for _ in 0..self.parameters.max_attempts {
+            let state = self.play_game();
+            if self.passes_filters(&state) {
+                return Some(state);
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::*;
+
+    use super::*;
+
+    fn parameters(target: PositionTarget, filters: Vec<PositionFilter>) -> PositionParameters {
+        PositionParameters {
+            target,
+            policy: MovePolicy::Random,
+            filters,
+            max_attempts: 100,
+        }
+    }
+
+    #[rstest]
+    #[case(PositionTarget::MoveCount(40))]
+    #[case(PositionTarget::EndpointDensity(0.05))]
+    fn positions_are_valid_and_reach_the_target(#[case] target: PositionTarget) {
+        let generator = PositionGenerator::new(parameters(target, vec![]), 0);
+
+        for state in generator.take(5) {
+            assert_eq!(state.validate(), vec![], "{state}");
+
+            if state.terminal_value(state.player).is_none() {
+                match target {
+                    PositionTarget::MoveCount(count) => assert_eq!(state.move_count, count),
+                    PositionTarget::EndpointDensity(_) => assert!(state.move_count > 0),
+                }
+            }
+        }
+    }
+
+    #[rstest]
+    fn seeds_give_the_same_positions() {
+        let positions = |seed| {
+            PositionGenerator::new(parameters(PositionTarget::MoveCount(30), vec![]), seed)
+                .take(3)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(positions(7), positions(7));
+        assert_ne!(positions(7), positions(8));
+    }
+
+    #[rstest]
+    fn positions_pass_the_filters() {
+        let filters = vec![
+            PositionFilter::NonTerminal,
+            PositionFilter::ToMove(SpicePlayer::Red),
+            PositionFilter::CenterReachable,
+        ];
+        let generator =
+            PositionGenerator::new(parameters(PositionTarget::MoveCount(301), filters), 0);
+
+        let positions: Vec<_> = generator.take(2).collect();
+
+        assert_eq!(positions.len(), 2);
+        for state in positions {
+            assert_eq!(state.player, SpicePlayer::Red);
+            assert!(state.terminal_value(state.player).is_none());
+            assert!(state
+                .available_moves()
+                .any(|m| state.apply_move(&m).grid.center_owner() == Some(SpicePlayer::Red)));
+        }
+    }
+
+    #[rstest]
+    fn generators_give_up_on_impossible_filters() {
+        // Blue always moves first, so it can't be their turn after an odd number of moves
+        let mut generator = PositionGenerator::new(
+            parameters(
+                PositionTarget::MoveCount(1),
+                vec![
+                    PositionFilter::NonTerminal,
+                    PositionFilter::ToMove(SpicePlayer::Blue),
+                ],
+            ),
+            0,
+        );
+
+        assert_eq!(generator.next(), None);
+    }
+
+    #[rstest]
+    fn guided_games_prefer_what_the_evaluator_likes() {
+        // only the mover's endpoints count, so guided play should spread out more
+        let mut weights = [0.; FEATURE_COUNT];
+        weights[0] = 1.;
+        let guided = PositionParameters {
+            policy: MovePolicy::Guided {
+                evaluator: LinearEvaluator { weights },
+                candidates: 8,
+            },
+            ..parameters(PositionTarget::MoveCount(20), vec![])
+        };
+
+        let endpoints = |parameters: PositionParameters| {
+            PositionGenerator::new(parameters, 0)
+                .take(5)
+                .map(|s| s.move_cache.endpoint_coords(SpicePlayer::Blue).len())
+                .sum::<usize>()
+        };
+
+        assert!(endpoints(guided) > endpoints(parameters(PositionTarget::MoveCount(20), vec![])));
+    }
+
+    #[rstest]
+    fn guided_games_with_no_candidates_still_play_on() {
+        let guided = PositionParameters {
+            policy: MovePolicy::Guided {
+                evaluator: LinearEvaluator {
+                    weights: [0.; FEATURE_COUNT],
+                },
+                candidates: 0,
+            },
+            ..parameters(PositionTarget::MoveCount(20), vec![])
+        };
+
+        for state in PositionGenerator::new(guided, 0).take(5) {
+            assert_eq!(state.move_count, 20);
+        }
+    }
+}
@@ -0,0 +1,102 @@
+//! Property tests for the Spice rules. Games are played from move choices picked by
+//! proptest, so a failure shrinks down to a short sequence of moves that causes it.
+
+use mcts::GameState;
+use proptest::{prelude::*, sample::Index};
+
+use super::{coord::*, direction::*, fixtures::*, moves::*, players::*, SpiceState};
+
+/// The standard start, and a tiny one where games get to the end quickly.
+fn any_start() -> impl Strategy<Value = SpiceState> {
+    prop_oneof![Just(SpiceState::initial_state()), Just(small_state())]
+}
+
+/// Moves from anywhere near the grid, most of which aren't legal.
+fn any_move() -> impl Strategy<Value = SpiceMove> {
+    let coord = || -7i8..=7;
+
+    (coord(), coord(), coord(), 0..Direction::ALL.len())
+        .prop_map(|(i, j, k, d)| SpiceMove::new(virt_d3(i, j, k), Direction::ALL[d]))
+}
+
+fn any_player() -> impl Strategy<Value = SpicePlayer> {
+    prop_oneof![Just(SpicePlayer::Blue), Just(SpicePlayer::Red)]
+}
+
+/// Every position from `start` on, playing the legal move each of `choices` picks until
+/// they run out or the game ends.
+fn play(start: SpiceState, choices: &[Index]) -> Vec<SpiceState> {
+    let mut states = vec![start];
+
+    for choice in choices {
+        let state = states.last().unwrap();
+        let moves: Vec<_> = state.available_moves().collect();
+        if moves.is_empty() {
+            break;
+        }
+
+        states.push(state.apply_move(choice.get(&moves)));
+    }
+
+    states
+}
+
+proptest! {
+    // full size games are slow to check in debug builds
+    #![proptest_config(ProptestConfig::with_cases(32))]
+
+    #[test]
+    fn legal_games_stay_valid(
+        start in any_start(),
+        choices in prop::collection::vec(any::<Index>(), 0..100),
+    ) {
+        for state in play(start, &choices) {
+            prop_assert_eq!(state.validate(), vec![], "{}", state);
+        }
+    }
+
+    #[test]
+    fn cached_moves_match_the_grid(
+        start in any_start(),
+        choices in prop::collection::vec(any::<Index>(), 0..100),
+    ) {
+        for state in play(start, &choices) {
+            prop_assert_eq!(&state.move_cache, &MoveCache::from_grid(&state.grid), "{}", state);
+        }
+    }
+
+    #[test]
+    fn every_legal_move_applies(
+        start in any_start(),
+        choices in prop::collection::vec(any::<Index>(), 0..100),
+    ) {
+        let state = play(start, &choices).pop().unwrap();
+
+        for move_ in state.available_moves() {
+            prop_assert_eq!(state.check_move(&move_), Ok(()));
+
+            let next = state.apply_move(&move_);
+            prop_assert_eq!(next.player, state.player.opponent());
+            prop_assert_eq!(next.move_count, state.move_count + 1);
+        }
+    }
+
+    #[test]
+    fn checked_moves_are_legal_moves(
+        start in any_start(),
+        choices in prop::collection::vec(any::<Index>(), 0..100),
+        player in any_player(),
+        move_ in any_move(),
+    ) {
+        let state = play(start, &choices).pop().unwrap();
+        let legal = player == state.player && state.available_moves().any(|m| m == move_);
+
+        match state.try_apply_move(player, &move_) {
+            Ok(next) => {
+                prop_assert!(legal, "{} shouldn't be allowed in {}", move_, state);
+                prop_assert_eq!(next, state.apply_move(&move_));
+            }
+            Err(_) => prop_assert!(!legal, "{} should be allowed in {}", move_, state),
+        }
+    }
+}
@@ -0,0 +1,464 @@
+//! Game records, in the spirit of chess's PGN: a header of `[Name "value"]` tags, a blank
+//! line, then the numbered moves in move notation and the result.
+//!
+//! ```text
+//! [Blue "Alice"]
+//! [Red "AI"]
+//! [Config "{\"radius\":5.2,...}"]
+//! [Result "0-1"]
+//!
+//! 1. 3,3,3:DS -3,-3,-3:UN
+//! 2. 3,3,3:NE -3,-3,-3:SW
+//! 0-1
+//! ```
+//!
+//! Values are quoted, with `\` escaping quotes and backslashes. Structured values, like
+//! search parameters and the board config, are stored as JSON.
+
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+use mcts::{GameState, SearchParameters};
+use serde::{Deserialize, Serialize};
+
+use super::{config::*, moves::*, notation::*, players::*, SpiceState};
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct GameRecord {
+    pub blue: Option<String>,
+    pub red: Option<String>,
+    /// When the game was played, conventionally as `YYYY.MM.DD`.
+    pub date: Option<String>,
+    /// Seed of any randomness the AI used, so that the game can be reproduced.
+    pub seed: Option<u64>,
+    /// Search parameters, if Blue was played by the AI.
+    pub blue_search: Option<SearchParameters>,
+    /// Search parameters, if Red was played by the AI.
+    pub red_search: Option<SearchParameters>,
+    pub config: SpiceConfig,
+    pub result: GameResult,
+    /// Any tags that aren't one of the above, in the order they were read.
+    pub other_tags: Vec<(String, String)>,
+    pub moves: Vec<SpiceMove>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default, Serialize, Deserialize)]
+pub enum GameResult {
+    BlueWins,
+    RedWins,
+    Draw,
+    #[default]
+    Unfinished,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct RecordParseError {
+    /// Line number of the problem, starting from 1.
+    pub line: usize,
+    pub kind: RecordErrorKind,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum RecordErrorKind {
+    /// A header line wasn't `[Name "value"]`.
+    MalformedTag,
+    DuplicateTag(String),
+    /// The value of a tag with a known meaning couldn't be understood.
+    InvalidTagValue(String),
+    /// A tag came after the moves had started.
+    TagAfterMoves,
+    InvalidMove(MoveParseError),
+    /// A move number didn't match how many moves came before it.
+    WrongMoveNumber(String),
+    /// The result after the moves doesn't match the `Result` tag.
+    ResultMismatch,
+    /// Something followed the result.
+    TextAfterResult,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ReplayError {
+    InvalidConfig(ConfigError),
+    /// The move at index `ply` can't be played in the position before it.
+    IllegalMove {
+        ply: usize,
+        error: SpiceRuleError,
+    },
+    /// The game ended before the move at index `ply`.
+    GameAlreadyOver {
+        ply: usize,
+    },
+    /// The recorded result isn't how the replayed game ended.
+    ResultMismatch {
+        recorded: GameResult,
+        actual: GameResult,
+    },
+}
+
+impl GameResult {
+    /// The result of a game that reached `state`.
+    pub fn of(state: &SpiceState) -> Self {
+        match state.terminal_value(SpicePlayer::Blue) {
+            None => Self::Unfinished,
+            Some(v) if v > 0. => Self::BlueWins,
+            Some(v) if v < 0. => Self::RedWins,
+            Some(_) => Self::Draw,
+        }
+    }
+
+    fn notation(self) -> &'static str {
+        match self {
+            Self::BlueWins => "1-0",
+            Self::RedWins => "0-1",
+            Self::Draw => "1/2-1/2",
+            Self::Unfinished => "*",
+        }
+    }
+
+    fn from_notation(s: &str) -> Option<Self> {
+        [Self::BlueWins, Self::RedWins, Self::Draw, Self::Unfinished]
+            .into_iter()
+            .find(|r| r.notation() == s)
+    }
+}
+
+impl GameRecord {
+    /// Every position in the game, from the starting position to the one after the last
+    /// move.
+    pub fn replay(&self) -> Result<Vec<SpiceState>, ReplayError> {
+        let start = SpiceState::from_config(&self.config).map_err(ReplayError::InvalidConfig)?;
+        let states = replay_moves(start, &self.moves)?;
+
+        let actual = GameResult::of(states.last().unwrap());
+        if actual != self.result {
+            return Err(ReplayError::ResultMismatch {
+                recorded: self.result,
+                actual,
+            });
+        }
+
+        Ok(states)
+    }
+
+    fn tags(&self) -> Vec<(String, String)> {
+        let json = |p: &SearchParameters| serde_json::to_string(p).unwrap();
+
+        let known = [
+            ("Blue", self.blue.clone()),
+            ("Red", self.red.clone()),
+            ("Date", self.date.clone()),
+            ("Seed", self.seed.map(|s| s.to_string())),
+            ("BlueSearch", self.blue_search.as_ref().map(json)),
+            ("RedSearch", self.red_search.as_ref().map(json)),
+            ("Config", Some(serde_json::to_string(&self.config).unwrap())),
+            ("Result", Some(self.result.notation().to_owned())),
+        ];
+
+        known
+            .into_iter()
+            .filter_map(|(name, value)| value.map(|v| (name.to_owned(), v)))
+            .chain(self.other_tags.iter().cloned())
+            .collect()
+    }
+
+    /// Stores a tag's value in the matching field, returning whether the tag was known.
+    fn set_tag(&mut self, name: &str, value: &str) -> Result<bool, RecordErrorKind> {
+        let invalid = || RecordErrorKind::InvalidTagValue(name.to_owned());
+        let search = || serde_json::from_str(value).map_err(|_| invalid());
+
+        match name {
+            "Blue" => self.blue = Some(value.to_owned()),
+            "Red" => self.red = Some(value.to_owned()),
+            "Date" => self.date = Some(value.to_owned()),
+            "Seed" => self.seed = Some(value.parse().map_err(|_| invalid())?),
+            "BlueSearch" => self.blue_search = Some(search()?),
+            "RedSearch" => self.red_search = Some(search()?),
+            "Config" => self.config = serde_json::from_str(value).map_err(|_| invalid())?,
+            "Result" => self.result = GameResult::from_notation(value).ok_or_else(invalid)?,
+            _ => return Ok(false),
+        }
+
+        Ok(true)
+    }
+}
+
+/// Every position from `start` on, playing each of `moves` in turn, to the one after the
+/// last. Fails on the first move that can't be played.
+pub fn replay_moves(
+    start: SpiceState,
+    moves: &[SpiceMove],
+) -> Result<Vec<SpiceState>, ReplayError> {
+    let mut states = vec![start];
+
+    for (ply, move_) in moves.iter().enumerate() {
+        let state = states.last().unwrap();
+
+        if state.is_over() {
+            return Err(ReplayError::GameAlreadyOver { ply });
+        }
+
+        state
+            .check_move(move_)
+            .map_err(|error| ReplayError::IllegalMove { ply, error })?;
+
+        states.push(state.apply_move(move_));
+    }
+
+    Ok(states)
+}
+
+impl Display for GameRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, value) in self.tags() {
+            let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+            writeln!(f, "[{name} \"{value}\"]")?;
+        }
+
+        writeln!(f)?;
+
+        // Blue always moves first, so each numbered line is a Blue move and a Red reply
+        for (i, pair) in self.moves.chunks(2).enumerate() {
+            write!(f, "{}.", i + 1)?;
+            for move_ in pair {
+                write!(f, " {move_}")?;
+            }
+            writeln!(f)?;
+        }
+
+        writeln!(f, "{}", self.result.notation())
+    }
+}
+
+impl FromStr for GameRecord {
+    type Err = RecordParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut record = GameRecord::default();
+        let mut seen_tags: Vec<String> = Vec::new();
+        let mut in_moves = false;
+        let mut final_result = None;
+
+        for (index, line) in s.lines().enumerate() {
+            let error = |kind| RecordParseError {
+                line: index + 1,
+                kind,
+            };
+            let line = line.trim();
+
+            if line.is_empty() {
+                continue;
+            }
+
+            if line.starts_with('[') {
+                if in_moves {
+                    return Err(error(RecordErrorKind::TagAfterMoves));
+                }
+
+                let (name, value) =
+                    parse_tag(line).ok_or_else(|| error(RecordErrorKind::MalformedTag))?;
+
+                if seen_tags.contains(&name) {
+                    return Err(error(RecordErrorKind::DuplicateTag(name)));
+                }
+
+                if !record.set_tag(&name, &value).map_err(error)? {
+                    record.other_tags.push((name.clone(), value));
+                }
+
+                seen_tags.push(name);
+                continue;
+            }
+
+            in_moves = true;
+
+            for token in line.split_whitespace() {
+                if final_result.is_some() {
+                    return Err(error(RecordErrorKind::TextAfterResult));
+                }
+
+                if let Some(result) = GameResult::from_notation(token) {
+                    final_result = Some(result);
+                } else if let Some(number) = token.strip_suffix('.') {
+                    if number.parse() != Ok(record.moves.len() / 2 + 1) {
+                        return Err(error(RecordErrorKind::WrongMoveNumber(token.to_owned())));
+                    }
+                } else {
+                    let move_ = token
+                        .parse()
+                        .map_err(|e| error(RecordErrorKind::InvalidMove(e)))?;
+                    record.moves.push(move_);
+                }
+            }
+        }
+
+        if let Some(result) = final_result {
+            if seen_tags.iter().any(|t| t == "Result") && result != record.result {
+                return Err(RecordParseError {
+                    line: s.lines().count(),
+                    kind: RecordErrorKind::ResultMismatch,
+                });
+            }
+
+            record.result = result;
+        }
+
+        Ok(record)
+    }
+}
+
+fn parse_tag(line: &str) -> Option<(String, String)> {
+    let inner = line.strip_prefix('[')?.strip_suffix(']')?;
+    let (name, quoted) = inner.split_once(' ')?;
+
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return None;
+    }
+
+    let quoted = quoted.strip_prefix('"')?.strip_suffix('"')?;
+
+    let mut value = String::new();
+    let mut chars = quoted.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => value.push(chars.next()?),
+            // an unescaped quote would have ended the value early
+            '"' => return None,
+            c => value.push(c),
+        }
+    }
+
+    Some((name.to_owned(), value))
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::*;
+
+    use super::*;
+    use crate::spice::{coord::*, fixtures::*, grid::*};
+
+    /// A config matching [small_state], so that games finish quickly.
+    #[fixture]
+    fn small_config(small_state: SpiceState) -> SpiceConfig {
+        SpiceConfig {
+            blue_endpoints: vec![virt_d3(1, 1, 0)],
+            red_endpoints: vec![virt_d3(-1, -1, 0)],
+            blocked: small_state
+                .grid
+                .enumerate_vc()
+                .filter(|(_, s)| **s == GridSpace::Blocked)
+                .map(|(c, _)| c)
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[fixture]
+    fn record(small_config: SpiceConfig) -> GameRecord {
+        let start = SpiceState::from_config(&small_config).unwrap();
+        let moves = first_moves(&start, usize::MAX);
+        let end = moves.iter().fold(start, |s, m| s.apply_move(m));
+
+        GameRecord {
+            blue: Some("Alice \"the \\ best\"".to_owned()),
+            red: Some("AI".to_owned()),
+            date: Some("2023.01.30".to_owned()),
+            seed: Some(42),
+            red_search: Some(SearchParameters {
+                exploration_factor: 1.5,
+                search_iterations: 100,
+                solver_node_budget: Some(1_000),
+                rollout_cutoff: None,
+            }),
+            config: small_config,
+            result: GameResult::of(&end),
+            other_tags: vec![("Event".to_owned(), "Testing".to_owned())],
+            moves,
+            ..Default::default()
+        }
+    }
+
+    #[rstest]
+    fn records_round_trip(record: GameRecord) {
+        let text = record.to_string();
+        assert_eq!(text.parse(), Ok(record));
+    }
+
+    #[rstest]
+    fn replay_reconstructs_every_position(record: GameRecord) {
+        let states = record.replay().unwrap();
+
+        assert_eq!(states.len(), record.moves.len() + 1);
+        assert_eq!(GameResult::of(states.last().unwrap()), record.result);
+        for (ply, state) in states.iter().enumerate() {
+            assert_eq!(state.move_count as usize, ply);
+        }
+    }
+
+    #[rstest]
+    fn minimal_records_use_defaults() {
+        let record: GameRecord = "1. 3,3,3:DS\n*\n".parse().unwrap();
+
+        assert_eq!(record.config, SpiceConfig::default());
+        assert_eq!(record.result, GameResult::Unfinished);
+        assert_eq!(record.replay().unwrap().len(), 2);
+    }
+
+    #[rstest]
+    #[case("[Blue Alice]", 1, RecordErrorKind::MalformedTag)]
+    #[case("[Blue \"Al\"ice\"]", 1, RecordErrorKind::MalformedTag)]
+    #[case("[Blue \"A\"]\n[Blue \"B\"]", 2, RecordErrorKind::DuplicateTag("Blue".to_owned()))]
+    #[case("[Seed \"-1\"]", 1, RecordErrorKind::InvalidTagValue("Seed".to_owned()))]
+    #[case("[Config \"{}\"]", 1, RecordErrorKind::InvalidTagValue("Config".to_owned()))]
+    #[case("[Result \"2-0\"]", 1, RecordErrorKind::InvalidTagValue("Result".to_owned()))]
+    #[case("1. 3,3,3:DS\n[Blue \"A\"]", 2, RecordErrorKind::TagAfterMoves)]
+    #[case(
+        "\n1. 3,3,3:XX",
+        2,
+        RecordErrorKind::InvalidMove(MoveParseError::InvalidDirection)
+    )]
+    #[case("2. 3,3,3:DS", 1, RecordErrorKind::WrongMoveNumber("2.".to_owned()))]
+    #[case("[Result \"1-0\"]\n\n*", 3, RecordErrorKind::ResultMismatch)]
+    #[case("1-0 3,3,3:DS", 1, RecordErrorKind::TextAfterResult)]
+    fn parse_errors_give_the_line(
+        #[case] text: &str,
+        #[case] line: usize,
+        #[case] kind: RecordErrorKind,
+    ) {
+        assert_eq!(
+            text.parse::<GameRecord>(),
+            Err(RecordParseError { line, kind })
+        );
+    }
+
+    #[rstest]
+    fn replay_rejects_bad_games(record: GameRecord) {
+        let mut illegal = record.clone();
+        illegal.moves.insert(1, illegal.moves[0].clone());
+        assert!(matches!(
+            illegal.replay(),
+            Err(ReplayError::IllegalMove { ply: 1, .. })
+        ));
+
+        let mut too_long = record.clone();
+        too_long.moves.push(too_long.moves[0].clone());
+        assert_eq!(
+            too_long.replay(),
+            Err(ReplayError::GameAlreadyOver {
+                ply: record.moves.len()
+            })
+        );
+
+        let mut wrong_result = record.clone();
+        wrong_result.result = GameResult::Unfinished;
+        assert_eq!(
+            wrong_result.replay(),
+            Err(ReplayError::ResultMismatch {
+                recorded: GameResult::Unfinished,
+                actual: record.result
+            })
+        );
+    }
+}
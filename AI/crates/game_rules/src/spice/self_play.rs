@@ -0,0 +1,203 @@
+//! Self-play data export: the AI plays full games of Spice against itself, recording
+//! every position it searched alongside what the search thought of it and how the game
+//! actually turned out. Output is JSONL, one [SelfPlayRecord] per line.
+
+use std::io::{self, Write};
+
+use mcts::{GameState, SearchParameters, Searcher};
+use serde::{Deserialize, Serialize};
+
+use super::{coord::*, grid::*, moves::*, players::*, SpiceState};
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct SelfPlayRecord {
+    /// Index of the game this position came from, within one export.
+    pub game: u32,
+    pub move_count: u16,
+    pub player: SpicePlayer,
+    /// Every space in the grid that isn't [GridSpace::Empty].
+    pub spaces: Vec<(VirtD3, GridSpace)>,
+    /// How many times the search visited each move from this position. Empty if `proven`.
+    pub visits: Vec<(SpiceMove, i32)>,
+    /// The search's estimate of this position, for `player`. Exact if `proven`.
+    pub value: f32,
+    /// Whether the solver proved the value of this position, so the move was played without
+    /// a search.
+    pub proven: bool,
+    pub chosen_move: SpiceMove,
+    /// How the game ended for `player`: 1 for a win, -1 for a loss, 0 for a draw.
+    pub outcome: f32,
+}
+
+/// Plays `games` games of self-play and writes every position from them to `writer`.
+/// Each game is written out as soon as it finishes.
+pub fn export_self_play(
+    writer: &mut impl Write,
+    parameters: &SearchParameters,
+    games: u32,
+) -> io::Result<()> {
+    for game in 0..games {
+        write_records(writer, &play_self_play_game(parameters, game))?;
+    }
+
+    Ok(())
+}
+
+pub fn write_records(writer: &mut impl Write, records: &[SelfPlayRecord]) -> io::Result<()> {
+    for record in records {
+        serde_json::to_writer(&mut *writer, record)?;
+        writeln!(writer)?;
+    }
+
+    writer.flush()
+}
+
+/// Plays one game from [SpiceState::initial_state] to the end, with each player using its
+/// own [Searcher] so that both sides get to reuse their trees between turns.
+pub fn play_self_play_game(parameters: &SearchParameters, game: u32) -> Vec<SelfPlayRecord> {
+    play_self_play_from(SpiceState::initial_state(), parameters, game)
+}
+
+fn play_self_play_from(
+    mut state: SpiceState,
+    parameters: &SearchParameters,
+    game: u32,
+) -> Vec<SelfPlayRecord> {
+    let mut blue = Searcher::new(parameters.clone());
+    let mut red = Searcher::new(parameters.clone());
+
+    let mut records = Vec::new();
+
+    while state.terminal_value(state.player).is_none() {
+        let searcher = match state.player {
+            SpicePlayer::Blue => &mut blue,
+            SpicePlayer::Red => &mut red,
+        };
+
+        let chosen_move = searcher.search(state.clone());
+        let (visits, value, proven) = match (searcher.last_solution(), searcher.root_statistics()) {
+            // the move was proven rather than searched, so there's no tree to report on
+            (Some(solution), _) => (vec![], solution.value, true),
+            (None, Some(stats)) => (
+                stats
+                    .children
+                    .iter()
+                    .map(|c| (c.move_.clone(), c.visits))
+                    .collect(),
+                stats.value(),
+                false,
+            ),
+            (None, None) => unreachable!("a search that didn't play a proven move built a tree"),
+        };
+
+        records.push(SelfPlayRecord {
+            game,
+            move_count: state.move_count,
+            player: state.player,
+            spaces: state
+                .grid
+                .enumerate_vc()
+                .filter(|(_, s)| **s != GridSpace::Empty)
+                .map(|(c, s)| (c, s.clone()))
+                .collect(),
+            visits,
+            value,
+            proven,
+            chosen_move: chosen_move.clone(),
+            outcome: 0.,
+        });
+
+        state = state.apply_move(&chosen_move);
+    }
+
+    for record in &mut records {
+        record.outcome = state
+            .terminal_value(record.player)
+            .expect("the game should be over");
+    }
+
+    records
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::*;
+
+    use super::*;
+    use crate::spice::fixtures::*;
+
+    #[fixture]
+    fn parameters() -> SearchParameters {
+        SearchParameters {
+            exploration_factor: std::f32::consts::FRAC_1_SQRT_2,
+            search_iterations: 4,
+            solver_node_budget: None,
+            rollout_cutoff: None,
+        }
+    }
+
+    #[rstest]
+    fn self_play_records_whole_game(parameters: SearchParameters, small_state: SpiceState) {
+        let records = play_self_play_from(small_state, &parameters, 0);
+
+        assert!(!records.is_empty());
+        for (ply, record) in records.iter().enumerate() {
+            assert_eq!(record.move_count as usize, ply);
+            assert!(record.proven || record.visits.iter().any(|(m, _)| *m == record.chosen_move));
+        }
+
+        let last = records.last().unwrap();
+        for record in &records {
+            let expected = if record.player == last.player {
+                last.outcome
+            } else {
+                -last.outcome
+            };
+            assert_eq!(record.outcome, expected);
+        }
+    }
+
+    #[rstest]
+    fn proven_positions_record_the_solved_value(parameters: SearchParameters) {
+        // blue's only move takes the center
+        let state = blocked_state(&[
+            (
+                virt_d3(2, 0, 0),
+                GridSpace::Endpoint {
+                    owner: SpicePlayer::Blue,
+                    connected_lines: 0,
+                },
+            ),
+            (virt_d3(1, 0, 0), GridSpace::Empty),
+            (virt_d3(0, 0, 0), GridSpace::Empty),
+        ]);
+        let parameters = SearchParameters {
+            solver_node_budget: Some(100),
+            ..parameters
+        };
+
+        let records = play_self_play_from(state, &parameters, 0);
+
+        assert_eq!(records.len(), 1);
+        assert!(records[0].proven);
+        assert_eq!(records[0].visits, vec![]);
+        assert_eq!(records[0].value, 1.);
+        assert_eq!(records[0].outcome, 1.);
+    }
+
+    #[rstest]
+    fn records_round_trip_through_jsonl(parameters: SearchParameters, small_state: SpiceState) {
+        let records = play_self_play_from(small_state, &parameters, 3);
+
+        let mut buf = Vec::new();
+        write_records(&mut buf, &records).unwrap();
+
+        let read: Vec<SelfPlayRecord> = std::str::from_utf8(&buf)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+
+        assert_eq!(read, records);
+    }
+}
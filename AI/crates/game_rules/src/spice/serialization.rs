@@ -0,0 +1,187 @@
+//! Serde representations for the Spice types that don't map directly onto their fields.
+//! A [Grid] is stored sparsely, as its radius and every space that isn't empty, instead of
+//! as its whole backing array. Grids and states carry a version number, so that clients
+//! can tell when the format has changed under them.
+
+use serde::{Deserialize, Serialize};
+
+use super::{config::*, coord::*, grid::*, moves::*, players::*, SpiceState};
+
+/// Version of the [Grid] and [SpiceState] representations. Bump this whenever either
+/// changes in a way that older readers couldn't understand.
+pub const SERIALIZATION_VERSION: u32 = 1;
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub(super) struct GridRepr {
+    version: u32,
+    radius: f32,
+    spaces: Vec<(VirtD3, GridSpace)>,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub(super) struct SpiceStateRepr {
+    version: u32,
+    grid: GridRepr,
+    player: SpicePlayer,
+    move_count: u16,
+    max_moves: u16,
+}
+
+impl From<Grid> for GridRepr {
+    fn from(grid: Grid) -> Self {
+        Self {
+            version: SERIALIZATION_VERSION,
+            radius: grid.radius(),
+            spaces: grid
+                .enumerate_vc()
+                .filter(|(_, s)| **s != GridSpace::Empty)
+                .map(|(c, s)| (c, s.clone()))
+                .collect(),
+        }
+    }
+}
+
+impl TryFrom<GridRepr> for Grid {
+    type Error = String;
+
+    fn try_from(repr: GridRepr) -> Result<Self, Self::Error> {
+        check_version(repr.version)?;
+
+        let mut grid = Grid::try_with_radius(repr.radius)
+            .ok_or_else(|| format!("{} is not a valid grid radius", repr.radius))?;
+
+        for (coord, space) in repr.spaces {
+            if coord == virt_d3(0, 0, 0) && space == GridSpace::Blocked {
+                return Err("the center can't be blocked".to_owned());
+            }
+
+            match grid.get_vc(coord) {
+                None => return Err(format!("{coord} is outside the grid")),
+                Some(GridSpace::Empty) => grid.set_vc_unchecked(coord, space),
+                Some(_) => return Err(format!("{coord} is listed more than once")),
+            }
+        }
+
+        Ok(grid)
+    }
+}
+
+impl From<SpiceState> for SpiceStateRepr {
+    fn from(state: SpiceState) -> Self {
+        Self {
+            version: SERIALIZATION_VERSION,
+            grid: state.grid.into(),
+            player: state.player,
+            move_count: state.move_count,
+            max_moves: state.max_moves,
+        }
+    }
+}
+
+impl TryFrom<SpiceStateRepr> for SpiceState {
+    type Error = String;
+
+    fn try_from(repr: SpiceStateRepr) -> Result<Self, Self::Error> {
+        check_version(repr.version)?;
+
+        let grid = Grid::try_from(repr.grid)?;
+        let move_cache = MoveCache::from_grid(&grid);
+
+        Ok(Self {
+            grid,
+            player: repr.player,
+            move_cache,
+            move_count: repr.move_count,
+            max_moves: repr.max_moves,
+        })
+    }
+}
+
+fn check_version(version: u32) -> Result<(), String> {
+    if version == SERIALIZATION_VERSION {
+        Ok(())
+    } else {
+        Err(format!(
+            "unsupported version {version}, expected {SERIALIZATION_VERSION}"
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mcts::GameState;
+    use rstest::*;
+    use serde_json::json;
+
+    use super::*;
+    use crate::spice::{direction::*, fixtures::*};
+
+    #[rstest]
+    fn state_json_is_sparse_and_versioned() {
+        // parsed back from text, since f32s only print exactly as text
+        let text = serde_json::to_string(&SpiceState::initial_state()).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&text).unwrap();
+
+        assert_eq!(
+            json,
+            json!({
+                "version": 1,
+                "grid": {
+                    "version": 1,
+                    "radius": 5.2,
+                    "spaces": [
+                        [{"i": -3, "j": -3, "k": -3}, {"Endpoint": {"owner": "Red", "connected_lines": 0}}],
+                        [{"i": 3, "j": 3, "k": 3}, {"Endpoint": {"owner": "Blue", "connected_lines": 0}}],
+                    ],
+                },
+                "player": "Blue",
+                "move_count": 0,
+                "max_moves": 400,
+            })
+        );
+    }
+
+    #[rstest]
+    fn played_states_round_trip(small_state: SpiceState) {
+        let mut state = small_state;
+
+        while state.terminal_value(state.player).is_none() {
+            let json = serde_json::to_string(&state).unwrap();
+            let loaded: SpiceState = serde_json::from_str(&json).unwrap();
+
+            assert_eq!(loaded.grid, state.grid);
+            assert_eq!(serde_json::to_string(&loaded).unwrap(), json);
+
+            let move_ = state.available_moves().next().unwrap();
+            state = state.apply_move(&move_);
+        }
+    }
+
+    #[rstest]
+    fn small_types_round_trip() {
+        let move_ = SpiceMove::new(virt_d3(1, -2, 3), Direction::UpWest);
+        let json = serde_json::to_string(&move_).unwrap();
+        assert_eq!(serde_json::from_str::<SpiceMove>(&json).unwrap(), move_);
+
+        let coord = real(2, -4, 0);
+        let json = serde_json::to_string(&coord).unwrap();
+        assert_eq!(json, r#"{"x":2,"y":-4,"z":0}"#);
+        assert_eq!(serde_json::from_str::<Real>(&json).unwrap(), coord);
+
+        let json = serde_json::to_string(&Axis::UsDn).unwrap();
+        assert_eq!(serde_json::from_str::<Axis>(&json).unwrap(), Axis::UsDn);
+    }
+
+    #[rstest]
+    #[case(json!({"version": 2, "radius": 5.2, "spaces": []}))]
+    #[case(json!({"version": 1, "radius": 0.5, "spaces": []}))]
+    #[case(json!({"version": 1, "radius": 5.2, "spaces": [[{"i": 5, "j": 5, "k": 5}, "Blocked"]]}))]
+    #[case(json!({"version": 1, "radius": 5.2, "spaces": [[{"i": 0, "j": 0, "k": 0}, "Blocked"]]}))]
+    #[case(json!({"version": 1, "radius": 5.2, "spaces": [
+        [{"i": 1, "j": 0, "k": 0}, "Blocked"],
+        [{"i": 1, "j": 0, "k": 0}, "Blocked"],
+    ]}))]
+    fn invalid_grids_are_rejected(#[case] json: serde_json::Value) {
+        assert!(serde_json::from_value::<Grid>(json).is_err());
+    }
+}
@@ -0,0 +1,174 @@
+//! Positions that are the same up to rotating or reflecting the grid, or swapping the
+//! players' colors, play out the same way. Mapping each to one canonical form lets opening
+//! books, transposition tables and training data treat them as one.
+
+use super::{coord::*, grid::*, moves::*, SpiceState};
+
+/// A position's canonical form, and how to get to it.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Canonical {
+    pub state: SpiceState,
+    /// Takes the original position's coordinates, directions and moves to the canonical
+    /// position's. Its inverse takes them back.
+    pub symmetry: Symmetry,
+    /// Whether the colors were swapped, in which case the canonical position's player to
+    /// move is the other color.
+    pub colors_swapped: bool,
+}
+
+impl Transform for SpiceState {
+    fn transformed(&self, symmetry: Symmetry) -> Self {
+        let grid = self.grid.transformed(symmetry);
+        let move_cache = MoveCache::from_grid(&grid);
+
+        Self {
+            grid,
+            move_cache,
+            ..self.clone()
+        }
+    }
+}
+
+impl SpiceState {
+    /// The same position, with each player's endpoints and turn given to the other.
+    pub fn with_colors_swapped(&self) -> Self {
+        let grid = self.grid.with_colors_swapped();
+        let move_cache = MoveCache::from_grid(&grid);
+
+        Self {
+            grid,
+            player: self.player.opponent(),
+            move_cache,
+            ..self.clone()
+        }
+    }
+
+    /// One position out of every one this is equivalent to under the grid's symmetries and
+    /// swapping colors. Every equivalent position gives the same one.
+    pub fn canonical(&self) -> Canonical {
+        let candidates = Symmetry::ALL
+            .into_iter()
+            .filter(|s| s.preserves_grids())
+            .flat_map(|symmetry| {
+                let state = self.transformed(symmetry);
+                let swapped = state.with_colors_swapped();
+
+                [
+                    Canonical {
+                        state,
+                        symmetry,
+                        colors_swapped: false,
+                    },
+                    Canonical {
+                        state: swapped,
+                        symmetry,
+                        colors_swapped: true,
+                    },
+                ]
+            });
+
+        // hashes don't depend on the order the candidates are tried in, so any rule for
+        // picking one works
+        candidates
+            .min_by_key(|c| c.state.zobrist_hash())
+            .expect("the identity should always be a candidate")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mcts::GameState;
+    use rstest::*;
+
+    use super::*;
+    use crate::spice::fixtures::*;
+
+    fn grid_symmetries() -> impl Iterator<Item = Symmetry> {
+        Symmetry::ALL.into_iter().filter(|s| s.preserves_grids())
+    }
+
+    #[rstest]
+    fn starting_layout_is_symmetric() {
+        let state = SpiceState::initial_state();
+
+        let inverted = state.transformed(Symmetry::INVERSION).with_colors_swapped();
+
+        assert_eq!(inverted.grid, state.grid);
+        assert_eq!(inverted.player, state.player.opponent());
+    }
+
+    #[rstest]
+    fn moves_commute_with_symmetries(small_state: SpiceState) {
+        let mut state = small_state;
+
+        while state.terminal_value(state.player).is_none() {
+            let move_ = state.available_moves().next().unwrap();
+
+            for symmetry in grid_symmetries() {
+                assert_eq!(
+                    state.apply_move(&move_).transformed(symmetry),
+                    state
+                        .transformed(symmetry)
+                        .apply_move(&move_.transformed(symmetry)),
+                    "{move_} under {symmetry:?}"
+                );
+            }
+
+            state = state.apply_move(&move_);
+        }
+    }
+
+    #[rstest]
+    fn equivalent_positions_share_a_canonical_form() {
+        let mut state = SpiceState::initial_state();
+        for _ in 0..6 {
+            state = state.apply_move(&state.available_moves().last().unwrap());
+        }
+
+        let canonical = state.canonical();
+
+        for symmetry in grid_symmetries() {
+            let equivalent = state.transformed(symmetry);
+
+            assert_eq!(equivalent.canonical().state, canonical.state);
+            assert_eq!(
+                equivalent.with_colors_swapped().canonical().state,
+                canonical.state
+            );
+        }
+    }
+
+    #[rstest]
+    fn canonical_form_says_how_to_reach_it(small_state: SpiceState) {
+        let canonical = small_state.canonical();
+
+        let mut reached = small_state.transformed(canonical.symmetry);
+        if canonical.colors_swapped {
+            reached = reached.with_colors_swapped();
+        }
+
+        assert_eq!(reached, canonical.state);
+        assert_eq!(
+            canonical
+                .state
+                .transformed(canonical.symmetry.inverse())
+                .grid,
+            if canonical.colors_swapped {
+                small_state.grid.with_colors_swapped()
+            } else {
+                small_state.grid
+            }
+        );
+    }
+
+    #[rstest]
+    #[should_panic]
+    fn grids_need_grid_symmetries() {
+        let lopsided = Symmetry::ALL
+            .into_iter()
+            .find(|s| !s.preserves_grids())
+            .unwrap();
+
+        Grid::default().transformed(lopsided);
+    }
+}
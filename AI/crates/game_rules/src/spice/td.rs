@@ -0,0 +1,170 @@
+//! TD(λ) training for [LinearEvaluator], by self-play. Each game is played greedily by the
+//! evaluator being trained (with some random exploration), and after every move its
+//! weights are nudged toward its own estimate of the next position, or toward the real
+//! result once the game is over.
+
+use mcts::GameState;
+use rand::{rngs::StdRng, seq::IteratorRandom, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use super::{evaluation::*, players::*, SpiceState};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TdParameters {
+    pub games: u32,
+    /// Also known as alpha.
+    pub learning_rate: f32,
+    /// How much credit earlier positions get for later errors, between 0 and 1.
+    pub lambda: f32,
+    /// Chance of playing a random move instead of the evaluator's favorite.
+    pub exploration: f32,
+    pub seed: u64,
+}
+
+impl Default for TdParameters {
+    fn default() -> Self {
+        Self {
+            games: 100,
+            learning_rate: 0.01,
+            lambda: 0.7,
+            exploration: 0.1,
+            seed: 0,
+        }
+    }
+}
+
+/// Trains `evaluator` in place over `parameters.games` games from
+/// [SpiceState::initial_state].
+pub fn train_td(evaluator: &mut LinearEvaluator, parameters: &TdParameters) {
+    train_td_from(evaluator, &SpiceState::initial_state(), parameters);
+}
+
+fn train_td_from(evaluator: &mut LinearEvaluator, start: &SpiceState, parameters: &TdParameters) {
+    let mut rng = StdRng::seed_from_u64(parameters.seed);
+
+    for _ in 0..parameters.games {
+        train_game(evaluator, start.clone(), parameters, &mut rng);
+    }
+}
+
+fn train_game(
+    evaluator: &mut LinearEvaluator,
+    mut state: SpiceState,
+    parameters: &TdParameters,
+    rng: &mut StdRng,
+) {
+    // every value is learned from Blue's perspective. since the features are relative to
+    // whoever they're for, that's enough to evaluate positions for Red too
+    const PERSPECTIVE: SpicePlayer = SpicePlayer::Blue;
+
+    let mut traces = [0.; FEATURE_COUNT];
+
+    while state.terminal_value(PERSPECTIVE).is_none() {
+        let x = features(&state, PERSPECTIVE);
+        let value = evaluator.value_of_features(&x);
+
+        // derivative of tanh(w . x) with respect to w
+        let slope = 1. - value * value;
+        for (e, x) in traces.iter_mut().zip(x) {
+            *e = parameters.lambda * *e + slope * x;
+        }
+
+        let next = choose_next_state(evaluator, &state, parameters.exploration, rng);
+        let target = next
+            .terminal_value(PERSPECTIVE)
+            .unwrap_or_else(|| evaluator.value(&next, PERSPECTIVE));
+
+        let error = target - value;
+        for (w, e) in evaluator.weights.iter_mut().zip(traces) {
+            *w += parameters.learning_rate * error * e;
+        }
+
+        state = next;
+    }
+}
+
+fn choose_next_state(
+    evaluator: &LinearEvaluator,
+    state: &SpiceState,
+    exploration: f32,
+    rng: &mut StdRng,
+) -> SpiceState {
+    let player = state.next_to_play();
+
+    if rng.gen::<f32>() < exploration {
+        let move_ = state
+            .available_moves()
+            .choose(rng)
+            .expect("there should be moves if the game isn't over");
+
+        return state.apply_move(&move_);
+    }
+
+    let mut best: Option<(f32, SpiceState)> = None;
+
+    for move_ in state.available_moves() {
+        let child = state.apply_move(&move_);
+        let value = child
+            .terminal_value(player)
+            .unwrap_or_else(|| evaluator.value(&child, player));
+
+        if best.as_ref().is_none_or(|(b, _)| value > *b) {
+            best = Some((value, child));
+        }
+    }
+
+    best.expect("there should be moves if the game isn't over")
+        .1
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::*;
+
+    use super::*;
+    use crate::spice::fixtures::*;
+
+    #[fixture]
+    fn parameters() -> TdParameters {
+        TdParameters {
+            games: 5,
+            learning_rate: 0.1,
+            ..Default::default()
+        }
+    }
+
+    #[fixture]
+    fn evaluator() -> LinearEvaluator {
+        LinearEvaluator {
+            weights: [0.1; FEATURE_COUNT],
+        }
+    }
+
+    #[rstest]
+    fn training_changes_weights(
+        parameters: TdParameters,
+        small_state: SpiceState,
+        evaluator: LinearEvaluator,
+    ) {
+        let mut trained = evaluator.clone();
+        train_td_from(&mut trained, &small_state, &parameters);
+
+        assert_ne!(trained, evaluator);
+        assert!(trained.weights.iter().all(|w| w.is_finite()));
+    }
+
+    #[rstest]
+    fn training_is_reproducible(
+        parameters: TdParameters,
+        small_state: SpiceState,
+        evaluator: LinearEvaluator,
+    ) {
+        let mut a = evaluator.clone();
+        let mut b = evaluator;
+
+        train_td_from(&mut a, &small_state, &parameters);
+        train_td_from(&mut b, &small_state, &parameters);
+
+        assert_eq!(a, b);
+    }
+}
@@ -0,0 +1,200 @@
+//! Structural invariants of Spice positions, which the rules should never break. Checking
+//! them after moves catches bookkeeping bugs that would otherwise only show up as strange
+//! games much later.
+
+use serde::{Deserialize, Serialize};
+
+use super::{coord::*, direction::*, grid::*, players::*, SpiceState};
+
+/// Something wrong with a position, found by [SpiceState::validate].
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub enum Violation {
+    /// The line through a segment doesn't carry on along its axis in `direction`, through
+    /// segments on the same axis, to an endpoint.
+    UnterminatedLine { at: VirtD3, direction: Direction },
+    /// An endpoint's `connected_lines` doesn't fit the lines next to it. Every segment next
+    /// to it on the axis towards it is the end of one of its lines, and the only others it
+    /// can have are lines with no segments, to endpoints of the same player next to it.
+    ConnectionMismatch {
+        at: VirtD3,
+        connected_lines: u8,
+        attached_lines: u8,
+    },
+    /// The center is blocked, which the game should have ended before it could be.
+    BlockedCenter,
+    /// More moves have been played than the game allows.
+    MoveCountPastLimit,
+    /// The grid has an endpoint the move cache doesn't know about.
+    MissingEndpoint { at: VirtD3, owner: SpicePlayer },
+    /// The move cache has an endpoint the grid doesn't.
+    StaleEndpoint { at: VirtD3, owner: SpicePlayer },
+    /// The move cache has the wrong moves for an endpoint.
+    StaleMoves { at: VirtD3, owner: SpicePlayer },
+    /// The move cache has the wrong number of moves for a player.
+    WrongMoveCount {
+        player: SpicePlayer,
+        cached: usize,
+        actual: usize,
+    },
+}
+
+impl SpiceState {
+    /// Every broken invariant of this position, in no particular order. Empty for any
+    /// position reached by legal moves from a valid one.
+    pub fn validate(&self) -> Vec<Violation> {
+        let mut violations = Vec::new();
+
+        for (at, space) in self.grid.enumerate_vc() {
+            match *space {
+                GridSpace::LineSegment { axis, .. } => {
+                    check_line(&self.grid, at, axis, &mut violations)
+                }
+                GridSpace::Endpoint {
+                    owner,
+                    connected_lines,
+                } => check_connections(&self.grid, at, owner, connected_lines, &mut violations),
+                GridSpace::Empty | GridSpace::Blocked => (),
+            }
+        }
+
+        if self.grid.get_vc(virt_d3(0, 0, 0)) == Some(&GridSpace::Blocked) {
+            violations.push(Violation::BlockedCenter);
+        }
+
+        if self.move_count > self.max_moves {
+            violations.push(Violation::MoveCountPastLimit);
+        }
+
+        violations.extend(self.move_cache.violations(&self.grid));
+
+        violations
+    }
+}
+
+fn check_line(grid: &Grid, at: VirtD3, axis: Axis, violations: &mut Vec<Violation>) {
+    let (dir1, dir2) = axis.directions();
+
+    for direction in [dir1, dir2] {
+        let end = grid
+            .ray(at, direction)
+            .map(|spot| grid.get_spot(spot))
+            .find(|space| !matches!(space, GridSpace::LineSegment { axis: a, .. } if *a == axis));
+
+        if !matches!(end, Some(GridSpace::Endpoint { .. })) {
+            violations.push(Violation::UnterminatedLine { at, direction });
+        }
+    }
+}
+
+fn check_connections(
+    grid: &Grid,
+    at: VirtD3,
+    owner: SpicePlayer,
+    connected_lines: u8,
+    violations: &mut Vec<Violation>,
+) {
+    let neighbors = Direction::ALL.map(|d| (d, grid.neighbor(at, d).and_then(|n| grid.get_vc(n))));
+
+    let attached_lines = neighbors
+        .iter()
+        .filter(
+            |(d, n)| matches!(n, Some(GridSpace::LineSegment { axis, .. }) if *axis == d.axis()),
+        )
+        .count() as u8;
+    let friendly_neighbors = neighbors
+        .iter()
+        .filter(|(_, n)| matches!(n, Some(GridSpace::Endpoint { owner: o, .. }) if *o == owner))
+        .count() as u8;
+
+    if connected_lines < attached_lines || connected_lines > attached_lines + friendly_neighbors {
+        violations.push(Violation::ConnectionMismatch {
+            at,
+            connected_lines,
+            attached_lines,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mcts::GameState;
+    use rand::{rngs::StdRng, seq::IteratorRandom, SeedableRng};
+    use rstest::*;
+
+    use super::*;
+    use crate::spice::{fixtures::*, moves::MoveCache};
+
+    #[rstest]
+    fn legal_games_stay_valid(small_state: SpiceState) {
+        let mut rng = StdRng::seed_from_u64(0);
+
+        // full size games are long, so fewer of them
+        for (start, games) in [(SpiceState::initial_state(), 2), (small_state, 20)] {
+            for _ in 0..games {
+                let mut state = start.clone();
+
+                while state.terminal_value(state.player).is_none() {
+                    assert_eq!(state.validate(), vec![], "{state}");
+
+                    let move_ = state.available_moves().choose(&mut rng).unwrap();
+                    state = state.apply_move(&move_);
+                }
+
+                assert_eq!(state.validate(), vec![], "{state}");
+            }
+        }
+    }
+
+    #[rstest]
+    #[case("5.2 0,3,2:NeSw;0,3,3:NeSw;0,3,4:R1 b 0 400", vec![
+        Violation::UnterminatedLine { at: virt_d3(0, 3, 2), direction: Direction::SouthWest },
+        Violation::UnterminatedLine { at: virt_d3(0, 3, 3), direction: Direction::SouthWest },
+    ])]
+    #[case("5.2 0,3,2:UnDs;0,3,3:R1 b 0 400", vec![
+        Violation::UnterminatedLine { at: virt_d3(0, 3, 2), direction: Direction::UpNorth },
+        Violation::UnterminatedLine { at: virt_d3(0, 3, 2), direction: Direction::DownSouth },
+        Violation::ConnectionMismatch { at: virt_d3(0, 3, 3), connected_lines: 1, attached_lines: 0 },
+    ])]
+    #[case("5.2 0,3,2:R2;0,3,3:NeSw;0,3,4:R1 b 0 400", vec![
+        Violation::ConnectionMismatch { at: virt_d3(0, 3, 2), connected_lines: 2, attached_lines: 1 },
+    ])]
+    #[case("5.2 - b 401 400", vec![Violation::MoveCountPastLimit])]
+    fn broken_grids_are_caught(#[case] notation: &str, #[case] expected: Vec<Violation>) {
+        let state: SpiceState = notation.parse().unwrap();
+
+        assert_eq!(state.validate(), expected);
+    }
+
+    #[rstest]
+    fn blocked_centers_are_caught() {
+        // notation won't describe a blocked center, so it has to be put there by hand
+        let mut state: SpiceState = "5.2 - b 0 400".parse().unwrap();
+        state
+            .grid
+            .set_vc_unchecked(virt_d3(0, 0, 0), GridSpace::Blocked);
+
+        assert_eq!(state.validate(), vec![Violation::BlockedCenter]);
+    }
+
+    #[rstest]
+    fn stale_caches_are_caught() {
+        let mut state: SpiceState = "5.2 3,3,3:B0;-3,-3,-3:R0 b 0 400".parse().unwrap();
+        let other: SpiceState = "5.2 3,3,3:B0;2,2,2:R0 b 0 400".parse().unwrap();
+        state.move_cache = other.move_cache.clone();
+
+        let violations = state.validate();
+
+        assert!(violations.contains(&Violation::MissingEndpoint {
+            at: virt_d3(-3, -3, -3),
+            owner: SpicePlayer::Red
+        }));
+        assert!(violations.contains(&Violation::StaleEndpoint {
+            at: virt_d3(2, 2, 2),
+            owner: SpicePlayer::Red
+        }));
+        assert_eq!(
+            MoveCache::from_grid(&state.grid).violations(&state.grid),
+            vec![]
+        );
+    }
+}
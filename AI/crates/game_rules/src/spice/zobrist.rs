@@ -0,0 +1,55 @@
+//! Keys for Zobrist hashing. Every feature of a position has its own pseudo-random key, and
+//! a position's hash is the XOR of the keys of the features it has, so a change only needs
+//! the keys of what changed.
+//!
+//! Keys are worked out from the feature rather than drawn from a table, so hashes are the
+//! same from run to run and can be saved.
+
+/// What a key is for. Keys for different kinds of feature never share an input.
+#[derive(Debug, Clone, Copy)]
+pub(super) enum Feature {
+    /// A cell being in a plane of a grid's bitboard, indexed by cell and plane.
+    Space,
+    /// Blue being next to play.
+    BlueToMove,
+    /// The number of moves played so far, indexed by the count.
+    MoveCount,
+}
+
+pub(super) fn key(feature: Feature, index: u64) -> u64 {
+    splitmix64(((feature as u64) << 56) ^ index)
+}
+
+// the finalizer from SplitMix64, which spreads every input bit across the whole output
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use rstest::*;
+
+    use super::*;
+
+    #[rstest]
+    fn keys_are_distinct() {
+        let keys: Vec<_> = [Feature::Space, Feature::BlueToMove, Feature::MoveCount]
+            .into_iter()
+            .flat_map(|f| (0..10_000).map(move |i| key(f, i)))
+            .collect();
+
+        assert_eq!(keys.iter().collect::<HashSet<_>>().len(), keys.len());
+    }
+
+    #[rstest]
+    fn keys_are_stable() {
+        // hashes may be saved, so the keys mustn't change
+        assert_eq!(key(Feature::Space, 0), splitmix64(0));
+        assert_eq!(splitmix64(0), 0xE220_A839_7B1D_CDAF);
+    }
+}
@@ -0,0 +1,176 @@
+//! Randomised checks that a [GameState] implementation keeps the promises search and
+//! the solver make about it
+
+use std::fmt::Debug;
+
+use rand::{rngs::StdRng, seq::IteratorRandom, SeedableRng};
+
+use crate::GameState;
+
+/// Plays `games` random games of `T`, each for up to `max_plies` moves, and panics if any
+/// state along the way breaks a contract of [GameState] that [crate::Searcher] and
+/// [crate::solve] rely on but the type system can't check:
+///
+/// - [GameState::initial_state] always gives the same state.
+/// - A state has moves exactly when it isn't terminal. A player with no moves has to be
+///   treated as the end of the game, since search can't go on from there.
+/// - [GameState::terminal_value] for the player who just moved is the negation of the value
+///   for the player to move.
+/// - [GameState::apply_move] gives the same state every time, and hands the turn to the
+///   other player.
+///
+/// Meant to be called from the tests of each game. Games are picked with `seed`, so
+/// failures can be reproduced.
+pub fn check_conformance<T>(games: usize, max_plies: usize, seed: u64)
+where
+    T: GameState + Debug,
+    T::Move: Debug,
+    T::Player: PartialEq + Debug,
+{
+    assert!(
+        T::initial_state() == T::initial_state(),
+        "initial_state should always give the same state"
+    );
+
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    for _ in 0..games {
+        let mut state = T::initial_state();
+        check_state(&state, None);
+
+        for _ in 0..max_plies {
+            let Some(move_) = state.available_moves().choose(&mut rng) else {
+                break;
+            };
+
+            let next = state.apply_move(&move_);
+            assert!(
+                next == state.apply_move(&move_),
+                "applying {move_:?} to {state:?} should always give the same state"
+            );
+            assert!(
+                next.next_to_play() != state.next_to_play(),
+                "{:?} should have to wait for their next turn after playing {move_:?} in {state:?}",
+                state.next_to_play()
+            );
+
+            check_state(&next, Some(state.next_to_play()));
+            state = next;
+        }
+    }
+}
+
+fn check_state<T>(state: &T, previous_player: Option<T::Player>)
+where
+    T: GameState + Debug,
+    T::Player: PartialEq + Debug,
+{
+    let player = state.next_to_play();
+    let value = state.terminal_value(player);
+    let has_moves = state.available_moves().next().is_some();
+
+    assert!(
+        value.is_some() != has_moves,
+        "{state:?} should have moves exactly when it isn't terminal, but has value {value:?} \
+         and {} moves",
+        if has_moves { "some" } else { "no" }
+    );
+
+    if let Some(opponent) = previous_player {
+        let opponent_value = state.terminal_value(opponent);
+        assert_eq!(
+            opponent_value,
+            value.map(|v| -v),
+            "{opponent:?} and {player:?} should get opposite values from {state:?}"
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::*;
+
+    use super::*;
+
+    const NOTHING: u8 = 0;
+    const MOVES_AFTER_THE_END: u8 = 1;
+    const STUCK: u8 = 2;
+    const SAME_VALUE_FOR_BOTH: u8 = 3;
+    const SAME_PLAYER_AGAIN: u8 = 4;
+
+    /// Take one stone from the pile; whoever takes the last one wins. `BROKEN` picks a
+    /// contract for it to break.
+    #[derive(Debug, PartialEq)]
+    struct Countdown<const BROKEN: u8> {
+        stones: u8,
+        first_player: bool,
+    }
+
+    impl<const BROKEN: u8> GameState for Countdown<BROKEN> {
+        type Move = u8;
+        type Player = bool;
+        type MoveIterator = std::vec::IntoIter<Self::Move>;
+
+        fn initial_state() -> Self {
+            Self {
+                stones: 5,
+                first_player: true,
+            }
+        }
+
+        fn available_moves(&self) -> Self::MoveIterator {
+            match BROKEN {
+                MOVES_AFTER_THE_END if self.stones == 0 => vec![0],
+                STUCK if self.stones == 1 => vec![],
+                _ if self.stones == 0 => vec![],
+                _ => vec![1],
+            }
+            .into_iter()
+        }
+
+        fn next_to_play(&self) -> Self::Player {
+            self.first_player
+        }
+
+        fn apply_move(&self, move_: &Self::Move) -> Self {
+            Self {
+                stones: self.stones - move_,
+                first_player: (BROKEN == SAME_PLAYER_AGAIN) == self.first_player,
+            }
+        }
+
+        fn terminal_value(&self, for_player: Self::Player) -> Option<f32> {
+            let won = for_player != self.first_player || BROKEN == SAME_VALUE_FOR_BOTH;
+            (self.stones == 0).then_some(if won { 1. } else { -1. })
+        }
+    }
+
+    #[rstest]
+    fn conforming_games_pass() {
+        check_conformance::<Countdown<NOTHING>>(3, 10, 0);
+    }
+
+    #[rstest]
+    #[should_panic(expected = "should have moves exactly when it isn't terminal")]
+    fn moves_after_the_end_are_caught() {
+        check_conformance::<Countdown<MOVES_AFTER_THE_END>>(3, 10, 0);
+    }
+
+    #[rstest]
+    #[should_panic(expected = "should have moves exactly when it isn't terminal")]
+    fn stuck_players_are_caught() {
+        check_conformance::<Countdown<STUCK>>(3, 10, 0);
+    }
+
+    #[rstest]
+    #[should_panic(expected = "should get opposite values")]
+    fn symmetric_values_are_caught() {
+        check_conformance::<Countdown<SAME_VALUE_FOR_BOTH>>(3, 10, 0);
+    }
+
+    #[rstest]
+    #[should_panic(expected = "should have to wait for their next turn")]
+    fn repeated_turns_are_caught() {
+        check_conformance::<Countdown<SAME_PLAYER_AGAIN>>(3, 10, 0);
+    }
+}
@@ -0,0 +1,11 @@
+use crate::game_state::GameState;
+
+/// Estimates the value of non-terminal states, so that rollouts can stop early instead of
+/// always playing to the end of the game.
+pub trait Evaluator<T>
+where
+    T: GameState,
+{
+    /// Should be on the same scale as [GameState::terminal_value], for the same player.
+    fn evaluate(&self, state: &T, for_player: T::Player) -> f32;
+}
@@ -19,8 +19,7 @@ pub trait GameState: PartialEq + Sized {
     /// implementation is not expected to check for that.
     fn apply_move(&self, move_: &Self::Move) -> Self;
 
-    /// Used in simulations and expansion to choose either promising or pseudo-random
-    /// moves.
+    /// Used in simulations to choose either promising or pseudo-random moves.
     fn default_policy(
         &self,
         moves: &mut impl Iterator<Item = Self::Move>,
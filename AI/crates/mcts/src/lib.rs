@@ -1,7 +1,13 @@
+mod conformance;
+mod evaluator;
 mod game_state;
 mod parameters;
 mod search;
+mod solver;
 
+pub use conformance::*;
+pub use evaluator::*;
 pub use game_state::*;
 pub use parameters::*;
 pub use search::*;
+pub use solver::*;
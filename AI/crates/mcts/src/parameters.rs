@@ -1,7 +1,17 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct SearchParameters {
     pub exploration_factor: f32,
     pub search_iterations: i32,
+    /// If set, [crate::Searcher::search] first tries to [crate::solve] the position
+    /// within this many nodes, and plays the proven move instead of searching if the
+    /// position isn't lost.
+    #[serde(default)]
+    pub solver_node_budget: Option<u32>,
+    /// If set, rollouts stop after this many moves and are scored by the searcher's
+    /// [crate::Evaluator] instead of being played out. Ignored for searchers made without
+    /// one.
+    #[serde(default)]
+    pub rollout_cutoff: Option<u32>,
 }
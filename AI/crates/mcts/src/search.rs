@@ -3,7 +3,11 @@
 use indextree::{Arena, NodeId};
 
 use super::SearchParameters;
-use crate::game_state::GameState;
+use crate::{
+    evaluator::Evaluator,
+    game_state::GameState,
+    solver::{solve, Outcome, Solution},
+};
 
 pub struct Searcher<T>
 where
@@ -12,6 +16,23 @@ where
     arena: Arena<MctsNode<T>>,
     previous_choice: Option<NodeId>,
     parameters: SearchParameters,
+    evaluator: Option<Box<dyn Evaluator<T> + Send>>,
+    last_solution: Option<Solution<T::Move>>,
+}
+
+/// What the tree learned about each move from the root of the most recent search. Scores
+/// are from the perspective of the player who was searching, who is also the player that
+/// would make each move.
+#[derive(Debug, PartialEq, Clone)]
+pub struct SearchStatistics<M> {
+    pub children: Vec<ChildStatistics<M>>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ChildStatistics<M> {
+    pub move_: M,
+    pub visits: i32,
+    pub score: f32,
 }
 
 #[derive(PartialEq, Debug)]
@@ -35,17 +56,50 @@ where
             arena: Arena::new(),
             previous_choice: None,
             parameters,
+            evaluator: None,
+            last_solution: None,
+        }
+    }
+
+    /// Creates a searcher that cuts rollouts off after
+    /// [SearchParameters::rollout_cutoff] moves and scores them with `evaluator`.
+    pub fn with_evaluator(
+        parameters: SearchParameters,
+        evaluator: impl Evaluator<T> + Send + 'static,
+    ) -> Self {
+        Searcher {
+            evaluator: Some(Box::new(evaluator)),
+            ..Self::new(parameters)
         }
     }
 
     pub fn search(&mut self, starting_state: T) -> T::Move {
+        self.last_solution = self.proven_solution(&starting_state);
+
+        if let Some(move_) = self
+            .last_solution
+            .as_ref()
+            .and_then(|s| s.best_move.clone())
+        {
+            // the tree didn't pick this move, so there's nothing worth keeping for next time
+            self.arena.clear();
+            self.previous_choice = None;
+
+            return move_;
+        }
+
         let player = starting_state.next_to_play();
         let root = self.starting_tree(starting_state);
 
         for _ in 0..self.parameters.search_iterations {
             let leaf = self.tree_policy(root);
             let leaf_state = &self.node(leaf).game_state;
-            let score = Self::rollout(leaf_state, player);
+            let score = self.rollout(leaf_state, player, 0);
+
+            // each node keeps its score for the player who moved into it, which is the
+            // searching player at odd depths and their opponent at even ones
+            let depth = leaf.ancestors(&self.arena).count() - 1;
+            let score = if depth % 2 == 1 { score } else { -score };
             self.backup_negamax(leaf, score);
         }
 
@@ -55,6 +109,40 @@ where
         self.node(max_child).move_.clone()
     }
 
+    /// Returns [None] if nothing has been searched yet, or if the last search played a
+    /// proven move without building a tree.
+    pub fn root_statistics(&self) -> Option<SearchStatistics<T::Move>> {
+        let root = self.arena.get(self.previous_choice?)?.parent()?;
+
+        let children = root
+            .children(&self.arena)
+            .map(|id| {
+                let node = self.node(id);
+                ChildStatistics {
+                    move_: node.move_.clone(),
+                    visits: node.visits,
+                    score: node.score,
+                }
+            })
+            .collect();
+
+        Some(SearchStatistics { children })
+    }
+
+    /// The solver's result for the position of the most recent search, if the search
+    /// played its proven move instead of building a tree.
+    pub fn last_solution(&self) -> Option<&Solution<T::Move>> {
+        self.last_solution.as_ref()
+    }
+
+    fn proven_solution(&self, state: &T) -> Option<Solution<T::Move>> {
+        let solution = solve(state, self.parameters.solver_node_budget?)?;
+
+        // every move in a lost position is equally bad in theory, so leave it to the tree
+        // to find the one that's most likely to trip up the opponent
+        (solution.outcome() != Outcome::Loss && solution.best_move.is_some()).then_some(solution)
+    }
+
     fn node(&self, id: NodeId) -> &MctsNode<T> {
         self.arena.get(id).unwrap().get()
     }
@@ -89,17 +177,24 @@ where
         }
     }
 
-    fn rollout(initial_state: &T, for_player: T::Player) -> f32 {
+    fn rollout(&self, initial_state: &T, for_player: T::Player, depth: u32) -> f32 {
         // wanted to do this iteratively, but was fighting the borrow checker. hopefully
         // we'll see some tail call optimization
-        if let Some(val) = initial_state.terminal_value(for_player) {
-            val
-        } else {
-            let (_, state) = initial_state
-                .default_policy(&mut initial_state.available_moves())
-                .expect("there should be moves to explore if the node is not terminal");
-
-            Self::rollout(&state, for_player)
+        let cut_off = self
+            .parameters
+            .rollout_cutoff
+            .is_some_and(|cutoff| depth >= cutoff);
+
+        match (initial_state.terminal_value(for_player), &self.evaluator) {
+            (Some(val), _) => val,
+            (None, Some(evaluator)) if cut_off => evaluator.evaluate(initial_state, for_player),
+            (None, _) => {
+                let (_, state) = initial_state
+                    .default_policy(&mut initial_state.available_moves())
+                    .expect("there should be moves to explore if the node is not terminal");
+
+                self.rollout(&state, for_player, depth + 1)
+            }
         }
     }
 
@@ -121,14 +216,15 @@ where
     fn expand(&mut self, node_id: NodeId) -> Option<NodeId> {
         let node = self.node_mut(node_id);
 
-        node.game_state
-            .default_policy(&mut node.unexpanded_moves)
-            .map(|(move_, game_state)| {
-                let child = self.arena.new_node(MctsNode::new(game_state, move_));
-                node_id.append(child, &mut self.arena);
+        // taken in order rather than through the default policy, since picking a random
+        // move from an iterator skips past the ones before it, and they'd never be tried
+        let move_ = node.unexpanded_moves.next()?;
+        let game_state = node.game_state.apply_move(&move_);
 
-                child
-            })
+        let child = self.arena.new_node(MctsNode::new(game_state, move_));
+        node_id.append(child, &mut self.arena);
+
+        Some(child)
     }
 
     /// exploration_factor is also known as c (Browne p. 9)
@@ -180,6 +276,20 @@ where
     }
 }
 
+impl<M> SearchStatistics<M> {
+    /// Mean score over every simulation that was run through the root's children.
+    pub fn value(&self) -> f32 {
+        let visits: i32 = self.children.iter().map(|c| c.visits).sum();
+        let score: f32 = self.children.iter().map(|c| c.score).sum();
+
+        if visits == 0 {
+            0.
+        } else {
+            score / visits as f32
+        }
+    }
+}
+
 impl<T> MctsNode<T>
 where
     T: GameState,
@@ -208,6 +318,7 @@ mod tests {
     use rstest::*;
 
     use super::*;
+    use crate::check_conformance;
 
     type MockGameState = i32;
 
@@ -257,6 +368,8 @@ mod tests {
         Searcher::new(SearchParameters {
             exploration_factor: FRAC_1_SQRT_2,
             search_iterations: 20,
+            solver_node_budget: None,
+            rollout_cutoff: None,
         })
     }
 
@@ -265,6 +378,14 @@ mod tests {
         parent: Option<NodeId>,
     ) -> (MockGameState, NodeId) {
         let state: MockGameState = rand::thread_rng().gen_range(0..10);
+        node_with_state(searcher, parent, state)
+    }
+
+    fn node_with_state(
+        searcher: &mut Searcher<MockGameState>,
+        parent: Option<NodeId>,
+        state: MockGameState,
+    ) -> (MockGameState, NodeId) {
         let node_id: NodeId;
 
         if let Some(parent_id) = parent {
@@ -319,6 +440,11 @@ mod tests {
         moves.for_each(drop);
     }
 
+    #[rstest]
+    fn mock_game_conforms() {
+        check_conformance::<MockGameState>(10, 100, 0);
+    }
+
     #[rstest]
     fn starting_tree_creates_new_tree(mut searcher: Searcher<MockGameState>) {
         let state: MockGameState = rand::random();
@@ -329,13 +455,12 @@ mod tests {
 
     #[rstest]
     fn starting_tree_finds_old_tree_and_detaches(mut searcher: Searcher<MockGameState>) {
-        // FIXME: have seen this test fail once, don't know what caused it. probably
-        // worth looking into property testing to find the failure state
         let node_1 = random_node(&mut searcher, None);
         let node_1_1 = random_node(&mut searcher, Some(node_1.1));
         let node_1_1_1 = random_node(&mut searcher, Some(node_1_1.1));
         let node_1_1_2 = random_node(&mut searcher, Some(node_1_1.1));
-        let node_1_2 = random_node(&mut searcher, Some(node_1.1));
+        // siblings with the same state would make it ambiguous which subtree to keep
+        let node_1_2 = node_with_state(&mut searcher, Some(node_1.1), node_1_1.0 + 1);
         let node_1_2_1 = random_node(&mut searcher, Some(node_1_2.1));
 
         searcher.previous_choice = Some(node_1.1);
@@ -430,8 +555,8 @@ mod tests {
 
     #[rstest]
     #[timeout(Duration::from_secs(1))]
-    fn rollout_terminates() {
-        let terminal_value = Searcher::rollout(&MockGameState::initial_state(), true);
+    fn rollout_terminates(searcher: Searcher<MockGameState>) {
+        let terminal_value = searcher.rollout(&MockGameState::initial_state(), true, 0);
         assert!(terminal_value.abs() >= 10.);
     }
 
@@ -453,6 +578,22 @@ mod tests {
         assert_eq!(get_score(node1), -score);
     }
 
+    #[rstest]
+    fn search_scores_children_for_the_searching_player(mut searcher: Searcher<MockGameState>) {
+        // from 8, moving to 9 leaves the opponent only moves that end the game in the
+        // searching player's favor, and moving to 11 ends it in the opponent's
+        searcher.search(8);
+
+        let stats = searcher.root_statistics().unwrap();
+        let mean = |move_: MockGameState| {
+            let child = stats.children.iter().find(|c| c.move_ == move_).unwrap();
+            child.score / child.visits as f32
+        };
+
+        assert!(mean(1) > 0., "{stats:?}");
+        assert!(mean(3) < 0., "{stats:?}");
+    }
+
     #[rstest]
     fn backup_negamax_avoids_unrelated_nodes(mut searcher: Searcher<MockGameState>) {
         let node1 = random_node(&mut searcher, None);
@@ -487,6 +628,18 @@ mod tests {
         assert_ne!(expanded.unwrap(), node.1);
     }
 
+    #[rstest]
+    fn expand_tries_every_move(mut searcher: Searcher<MockGameState>) {
+        let node = node_with_state(&mut searcher, None, 0);
+
+        let first = searcher.expand(node.1).unwrap();
+        let second = searcher.expand(node.1).unwrap();
+
+        assert_eq!(searcher.node(first).move_, 1);
+        assert_eq!(searcher.node(second).move_, 3);
+        assert_eq!(searcher.expand(node.1), None);
+    }
+
     #[rstest]
     fn expand_returns_none_if_node_is_fully_expanded(mut searcher: Searcher<MockGameState>) {
         let node = random_node(&mut searcher, None);
@@ -611,4 +764,52 @@ mod tests {
 
         assert_eq!(chosen.game_state, returned_game_state);
     }
+
+    #[rstest]
+    pub fn root_statistics_is_none_before_searching(searcher: Searcher<MockGameState>) {
+        assert_eq!(searcher.root_statistics(), None);
+    }
+
+    #[rstest]
+    pub fn root_statistics_covers_every_iteration(mut searcher: Searcher<MockGameState>) {
+        let move_ = searcher.search(MockGameState::initial_state());
+        let stats = searcher.root_statistics().unwrap();
+
+        let visits: i32 = stats.children.iter().map(|c| c.visits).sum();
+        assert_eq!(visits, searcher.parameters.search_iterations);
+        assert!(stats.children.iter().any(|c| c.move_ == move_));
+    }
+
+    struct ConstantEvaluator;
+
+    impl Evaluator<MockGameState> for ConstantEvaluator {
+        fn evaluate(&self, _state: &MockGameState, _for_player: bool) -> f32 {
+            0.5
+        }
+    }
+
+    #[rstest]
+    fn rollout_stops_at_cutoff_with_evaluator() {
+        let searcher = Searcher::with_evaluator(
+            SearchParameters {
+                exploration_factor: FRAC_1_SQRT_2,
+                search_iterations: 20,
+                solver_node_budget: None,
+                rollout_cutoff: Some(2),
+            },
+            ConstantEvaluator,
+        );
+
+        assert_eq!(searcher.rollout(&0, true, 0), 0.5);
+        // terminal states are still scored exactly
+        assert_eq!(searcher.rollout(&10, true, 0), 10.);
+    }
+
+    #[rstest]
+    fn rollout_ignores_cutoff_without_evaluator(mut searcher: Searcher<MockGameState>) {
+        searcher.parameters.rollout_cutoff = Some(0);
+
+        let terminal_value = searcher.rollout(&0, true, 0);
+        assert!(terminal_value.abs() >= 10.);
+    }
 }
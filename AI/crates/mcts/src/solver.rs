@@ -0,0 +1,217 @@
+//! Exhaustive negamax solver with alpha-beta pruning, for proving the outcome of
+//! positions that are small enough to search completely
+
+use crate::game_state::GameState;
+
+/// Theoretical result of a position, from the perspective of the player whose turn it is.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Outcome {
+    Win,
+    Loss,
+    Draw,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Solution<M> {
+    /// The exact value of the position under perfect play, as scored by
+    /// [GameState::terminal_value] for the player whose turn it is.
+    pub value: f32,
+    /// A move that achieves `value`. [None] if the position was already terminal.
+    pub best_move: Option<M>,
+    /// How many positions were visited to find the solution.
+    pub nodes: u32,
+}
+
+impl<M> Solution<M> {
+    pub fn outcome(&self) -> Outcome {
+        if self.value > 0. {
+            Outcome::Win
+        } else if self.value < 0. {
+            Outcome::Loss
+        } else {
+            Outcome::Draw
+        }
+    }
+}
+
+/// Attempts to prove the value of `state` by searching its entire game tree. Gives up and
+/// returns [None] if that would take more than `node_budget` positions.
+///
+/// Like [crate::Searcher], this assumes that players strictly alternate turns.
+pub fn solve<T>(state: &T, node_budget: u32) -> Option<Solution<T::Move>>
+where
+    T: GameState,
+{
+    let mut nodes = 1;
+
+    if let Some(value) = state.terminal_value(state.next_to_play()) {
+        return Some(Solution {
+            value,
+            best_move: None,
+            nodes,
+        });
+    }
+
+    let mut alpha = f32::NEG_INFINITY;
+    let mut best_move = None;
+
+    for move_ in state.available_moves() {
+        let child = state.apply_move(&move_);
+        let value = -negamax(&child, f32::NEG_INFINITY, -alpha, &mut nodes, node_budget)?;
+
+        if best_move.is_none() || value > alpha {
+            alpha = value;
+            best_move = Some(move_);
+        }
+    }
+
+    // a non-terminal state without moves breaks the GameState contract, so there's
+    // nothing we can prove about it
+    best_move.map(|move_| Solution {
+        value: alpha,
+        best_move: Some(move_),
+        nodes,
+    })
+}
+
+fn negamax<T>(state: &T, mut alpha: f32, beta: f32, nodes: &mut u32, budget: u32) -> Option<f32>
+where
+    T: GameState,
+{
+    *nodes += 1;
+    if *nodes > budget {
+        return None;
+    }
+
+    if let Some(value) = state.terminal_value(state.next_to_play()) {
+        return Some(value);
+    }
+
+    let mut best = None;
+
+    for move_ in state.available_moves() {
+        let child = state.apply_move(&move_);
+        let value = -negamax(&child, -beta, -alpha, nodes, budget)?;
+
+        if best.is_none_or(|b| value > b) {
+            best = Some(value);
+        }
+
+        alpha = alpha.max(value);
+        if alpha >= beta {
+            break;
+        }
+    }
+
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::*;
+
+    use super::*;
+    use crate::{check_conformance, SearchParameters, Searcher};
+
+    /// Take 1 or 2 stones from the pile; whoever takes the last stone wins. Piles that
+    /// are a multiple of 3 are lost for the player to move.
+    #[derive(Debug, PartialEq, Clone, Copy)]
+    struct Nim {
+        stones: u8,
+        first_player: bool,
+    }
+
+    impl GameState for Nim {
+        type Move = u8;
+        type Player = bool;
+        type MoveIterator = std::vec::IntoIter<Self::Move>;
+
+        fn initial_state() -> Self {
+            Nim {
+                stones: 10,
+                first_player: true,
+            }
+        }
+
+        fn available_moves(&self) -> Self::MoveIterator {
+            (1..=self.stones.min(2)).collect::<Vec<_>>().into_iter()
+        }
+
+        fn next_to_play(&self) -> Self::Player {
+            self.first_player
+        }
+
+        fn apply_move(&self, move_: &Self::Move) -> Self {
+            Nim {
+                stones: self.stones - move_,
+                first_player: !self.first_player,
+            }
+        }
+
+        fn terminal_value(&self, for_player: Self::Player) -> Option<f32> {
+            // the previous player took the last stone
+            (self.stones == 0).then_some(if for_player == self.first_player {
+                -1.
+            } else {
+                1.
+            })
+        }
+    }
+
+    fn nim(stones: u8) -> Nim {
+        Nim {
+            stones,
+            first_player: true,
+        }
+    }
+
+    #[rstest]
+    fn nim_conforms() {
+        check_conformance::<Nim>(10, 100, 0);
+    }
+
+    #[rstest]
+    #[case(1, Outcome::Win)]
+    #[case(2, Outcome::Win)]
+    #[case(3, Outcome::Loss)]
+    #[case(7, Outcome::Win)]
+    #[case(9, Outcome::Loss)]
+    #[case(11, Outcome::Win)]
+    fn solve_proves_nim(#[case] stones: u8, #[case] expected: Outcome) {
+        let solution = solve(&nim(stones), 100_000).unwrap();
+
+        assert_eq!(solution.outcome(), expected);
+        if expected == Outcome::Win {
+            assert_eq!((stones - solution.best_move.unwrap()) % 3, 0);
+        }
+    }
+
+    #[rstest]
+    fn solve_handles_terminal_states() {
+        let solution = solve(&nim(0), 1).unwrap();
+
+        assert_eq!(solution.outcome(), Outcome::Loss);
+        assert_eq!(solution.best_move, None);
+    }
+
+    #[rstest]
+    fn solve_gives_up_when_over_budget() {
+        assert_eq!(solve(&nim(20), 10), None);
+    }
+
+    #[rstest]
+    fn search_plays_proven_move() {
+        let mut searcher = Searcher::new(SearchParameters {
+            exploration_factor: 0.,
+            search_iterations: 1,
+            solver_node_budget: Some(100_000),
+            rollout_cutoff: None,
+        });
+
+        let move_ = searcher.search(nim(8));
+
+        assert_eq!(move_, 2);
+        assert_eq!(searcher.last_solution().unwrap().value, 1.);
+        assert_eq!(searcher.root_statistics(), None);
+    }
+}
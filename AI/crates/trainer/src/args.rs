@@ -0,0 +1,116 @@
+//! Just enough command line parsing for a handful of subcommands with `--name value`
+//! options.
+
+use std::{fmt::Display, str::FromStr};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+pub struct Args {
+    remaining: Vec<String>,
+}
+
+impl Args {
+    pub fn from_env() -> Self {
+        Self::new(std::env::args().skip(1))
+    }
+
+    pub fn new(args: impl IntoIterator<Item = String>) -> Self {
+        Self {
+            remaining: args.into_iter().collect(),
+        }
+    }
+
+    pub fn command(&mut self) -> Option<String> {
+        (!self.remaining.is_empty()).then(|| self.remaining.remove(0))
+    }
+
+    /// Takes the first argument that isn't an option or an option's value.
+    pub fn positional(&mut self, name: &str) -> Result<String> {
+        let mut i = 0;
+        while i < self.remaining.len() {
+            if self.remaining[i].starts_with("--") {
+                i += 2;
+            } else {
+                return Ok(self.remaining.remove(i));
+            }
+        }
+
+        Err(anyhow!("missing required argument <{name}>"))
+    }
+
+    pub fn option<T>(&mut self, name: &str) -> Result<Option<T>>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        let Some(i) = self.remaining.iter().position(|a| a == name) else {
+            return Ok(None);
+        };
+
+        if i + 1 >= self.remaining.len() {
+            bail!("{name} needs a value");
+        }
+
+        let value = self.remaining.remove(i + 1);
+        self.remaining.remove(i);
+
+        value
+            .parse()
+            .map(Some)
+            .map_err(|e| anyhow!("{e}"))
+            .with_context(|| format!("invalid value for {name}: {value}"))
+    }
+
+    /// Errors if there are any arguments that nothing asked for.
+    pub fn finish(self) -> Result<()> {
+        if self.remaining.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!(
+                "unexpected arguments: {}",
+                self.remaining.join(" ")
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::*;
+
+    use super::*;
+
+    fn args(s: &str) -> Args {
+        Args::new(s.split_whitespace().map(String::from))
+    }
+
+    #[rstest]
+    fn parses_command_options_and_positionals() {
+        let mut a = args("self-play --games 3 out.jsonl");
+
+        assert_eq!(a.command().as_deref(), Some("self-play"));
+        assert_eq!(a.option::<u32>("--games").unwrap(), Some(3));
+        assert_eq!(a.option::<u32>("--iterations").unwrap(), None);
+        assert_eq!(a.positional("output").unwrap(), "out.jsonl");
+        assert!(a.finish().is_ok());
+    }
+
+    #[rstest]
+    fn positional_skips_option_values() {
+        let mut a = args("--games 3 out.jsonl");
+
+        assert_eq!(a.positional("output").unwrap(), "out.jsonl");
+    }
+
+    #[rstest]
+    #[case("--games")]
+    #[case("--games many")]
+    fn bad_options_error(#[case] s: &str) {
+        assert!(args(s).option::<u32>("--games").is_err());
+    }
+
+    #[rstest]
+    fn finish_rejects_leftovers() {
+        assert!(args("what").finish().is_err());
+    }
+}
@@ -0,0 +1,118 @@
+mod args;
+
+use std::{fs::File, io::BufWriter, path::Path};
+
+use anyhow::{Context, Result};
+use game_rules::spice::{
+    export_self_play, generate_opening_book, train_td, BookParameters, LinearEvaluator,
+    TdParameters,
+};
+use mcts::SearchParameters;
+
+use self::args::Args;
+
+const USAGE: &str = "\
+usage: trainer <command> [options]
+
+commands:
+    self-play <output.jsonl>    play Spice games against itself and export every position
+        --games <n>             number of games to play (default 1)
+        --iterations <n>        search iterations per move (default 1000)
+        --exploration <c>       UCB1 exploration factor (default 1/sqrt(2))
+        --solver-budget <n>     try to solve positions within n nodes before searching
+
+    td-train <weights.json>     train a linear evaluator by TD(lambda) self-play, starting
+                                from the weights in the file if it already exists
+        --games <n>             number of games to play (default 100)
+        --learning-rate <a>     (default 0.01)
+        --lambda <l>            (default 0.7)
+        --exploration <e>       chance of playing a random move (default 0.1)
+        --seed <n>              (default 0)
+
+    opening-book <book.json>    search the first few plies of Spice and save the results
+        --plies <n>             how deep the book goes (default 4)
+        --width <n>             moves to follow from each position (default 3)
+        --iterations <n>        search iterations per position (default 100000)
+        --exploration <c>       UCB1 exploration factor (default 1/sqrt(2))
+        --solver-budget <n>     try to solve positions within n nodes before searching";
+
+pub fn main() -> Result<()> {
+    let mut args = Args::from_env();
+
+    match args.command().as_deref() {
+        Some("self-play") => self_play(args),
+        Some("td-train") => td_train(args),
+        Some("opening-book") => opening_book(args),
+        _ => {
+            eprintln!("{USAGE}");
+            std::process::exit(2);
+        }
+    }
+}
+
+fn self_play(mut args: Args) -> Result<()> {
+    let output = args.positional("output")?;
+    let games = args.option("--games")?.unwrap_or(1);
+    let parameters = search_parameters(&mut args, 1000)?;
+    args.finish()?;
+
+    let file =
+        File::create(&output).with_context(|| format!("should be able to create {output}"))?;
+    export_self_play(&mut BufWriter::new(file), &parameters, games)
+        .context("should be able to write self-play records")
+}
+
+fn td_train(mut args: Args) -> Result<()> {
+    let path = args.positional("weights")?;
+
+    let defaults = TdParameters::default();
+    let parameters = TdParameters {
+        games: args.option("--games")?.unwrap_or(defaults.games),
+        learning_rate: args
+            .option("--learning-rate")?
+            .unwrap_or(defaults.learning_rate),
+        lambda: args.option("--lambda")?.unwrap_or(defaults.lambda),
+        exploration: args
+            .option("--exploration")?
+            .unwrap_or(defaults.exploration),
+        seed: args.option("--seed")?.unwrap_or(defaults.seed),
+    };
+    args.finish()?;
+
+    let mut evaluator = if Path::new(&path).exists() {
+        LinearEvaluator::load(&path).with_context(|| format!("should be able to load {path}"))?
+    } else {
+        LinearEvaluator::default()
+    };
+
+    train_td(&mut evaluator, &parameters);
+
+    evaluator
+        .save(&path)
+        .with_context(|| format!("should be able to save {path}"))
+}
+
+fn opening_book(mut args: Args) -> Result<()> {
+    let path = args.positional("book")?;
+    let parameters = BookParameters {
+        plies: args.option("--plies")?.unwrap_or(4),
+        width: args.option("--width")?.unwrap_or(3),
+        search: search_parameters(&mut args, 100_000)?,
+    };
+    args.finish()?;
+
+    generate_opening_book(&parameters)
+        .save(&path)
+        .with_context(|| format!("should be able to save {path}"))
+}
+
+fn search_parameters(args: &mut Args, default_iterations: i32) -> Result<SearchParameters> {
+    Ok(SearchParameters {
+        exploration_factor: args
+            .option("--exploration")?
+            .unwrap_or(std::f32::consts::FRAC_1_SQRT_2),
+        search_iterations: args.option("--iterations")?.unwrap_or(default_iterations),
+        solver_node_budget: args.option("--solver-budget")?,
+        rollout_cutoff: None,
+    })
+}
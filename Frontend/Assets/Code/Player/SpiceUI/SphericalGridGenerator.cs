@@ -47,6 +47,10 @@ public class SphericalGridGenerator : MonoBehaviour
         [SerializeField]
         private GameObject nodePrefab;
 
+        // a SpiceConfig exported from the AI, whose radius overrides gridConstant when set
+        [SerializeField]
+        private TextAsset config;
+
         private readonly List<(Vector3, Vector3)> _lines = new();
         private readonly List<Vector3> _positions = new();
 
@@ -84,6 +88,9 @@ private void GeneratePositions()
             _lines.Clear();
 
             var rotation = Quaternion.Euler(this.rotation);
+            var gridConstant = config
+                ? JsonUtility.FromJson<SpiceConfig>(config.text).radius
+                : this.gridConstant;
 
             var bound = Mathf.Floor(gridConstant);
             for (var i = -bound; i <= bound; i++)
@@ -111,5 +118,12 @@ private void GeneratePositions()
 
             Debug.Log(_positions.Count.ToString());
         }
+
+        // only the parts of the AI's SpiceConfig that affect the grid's shape
+        [Serializable]
+        private class SpiceConfig
+        {
+            public float radius;
+        }
     }
 }